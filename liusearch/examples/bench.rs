@@ -0,0 +1,80 @@
+//! `cargo run --release --example bench -- <names.txt> <pattern>...` compares a full scan with
+//! the trigram index. Patterns prefixed with `re:` are regexes, all others are plain.
+
+use std::{
+    fs::File,
+    io::{BufRead, BufReader},
+    path::Path,
+    time::Instant,
+};
+
+use anyhow::{bail, ensure};
+use rayon::prelude::*;
+
+use liusearch::model::*;
+use liusearch::trigram::TrigramIndex;
+
+fn main() -> anyhow::Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let [path, patterns @ ..] = args.as_slice() else {
+        bail!("Usage: bench <names.txt> <pattern>...");
+    };
+
+    let start = Instant::now();
+    let mut builder = CorpusBuilder::default();
+    let source = builder.add_source(Path::new(path));
+    for line in BufReader::new(File::open(path)?).lines() {
+        builder.push(&line?, source)?;
+    }
+    let (corpus, _) = builder.finish();
+    println!("Loaded {} names in {:.2?}", corpus.len(), start.elapsed());
+
+    let start = Instant::now();
+    let index = TrigramIndex::new(&corpus);
+    println!("Built the trigram index in {:.2?}", start.elapsed());
+
+    for pattern in patterns {
+        let (mode, literal) = match pattern.strip_prefix("re:") {
+            Some(regex) => (SearchMode::RegEx, regex),
+            None => (SearchMode::Plain, pattern.as_str()),
+        };
+        let settings = SearchSettings {
+            mode,
+            lev: LevenshteinSettings::default(),
+            terms: Vec::new(),
+            confusables: Vec::new(),
+            folding: Folding::default(),
+            exceptions: Exceptions::new(),
+        };
+        let searcher = Searcher::new(&literal.to_ascii_lowercase(), &settings)?;
+
+        let start = Instant::now();
+        let scanned: usize = corpus
+            .par_chunks(100_000)
+            .map(|users| users.filter(|u| searcher.matches(u.id).is_some()).count())
+            .sum();
+        let scan_time = start.elapsed();
+
+        let start = Instant::now();
+        let Some(candidates) = searcher.literals().and_then(|l| index.candidates(&l)) else {
+            println!("{pattern}: {scanned} matches, scan {scan_time:.2?}, can't use the index");
+            continue;
+        };
+        let indexed = candidates
+            .par_iter()
+            .filter(|&&i| searcher.matches(corpus.get(i as usize).id).is_some())
+            .count();
+        let index_time = start.elapsed();
+
+        ensure!(
+            indexed == scanned,
+            "{pattern}: {indexed} matches with the index instead of {scanned}"
+        );
+        println!(
+            "{pattern}: {scanned} matches, scan {scan_time:.2?}, index {index_time:.2?} \
+             ({} candidates)",
+            candidates.len()
+        );
+    }
+    Ok(())
+}
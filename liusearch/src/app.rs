@@ -1,22 +1,35 @@
 use std::{
+    cell::OnceCell,
     collections::{HashMap, HashSet},
     fmt::Display,
     io::{BufRead, BufReader, Read},
-    path::PathBuf,
-    sync::{atomic::Ordering::SeqCst, Arc, Mutex},
+    ops::Range,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering::SeqCst},
+        Arc, Mutex,
+    },
     time::Duration,
 };
 
-use anyhow::{bail, ensure};
-use chrono::Utc;
+use aho_corasick::{AhoCorasick, MatchKind};
+use anyhow::{bail, ensure, Context};
+use chrono::{NaiveDate, Utc};
 use copypasta::ClipboardProvider;
 use eframe::{
-    egui::{self, vec2, Button, DragValue, Grid, Key, Layout, ProgressBar, RichText, TextEdit},
-    emath::Align,
-    epaint::Color32,
+    egui::{
+        self, text::LayoutJob, vec2, Button, DragValue, Grid, Id, Key, LayerId, Layout, Order,
+        ProgressBar, RichText, TextEdit, TextFormat, TextStyle,
+    },
+    emath::{Align, Align2},
+    epaint::{Color32, Stroke},
 };
 use num_format::{Locale, ToFormattedString};
-use pgp::Deserializable;
+use pgp::{
+    composed::{Deserializable, Message, SignedSecretKey, TheRing},
+    types::Password,
+};
 use progress_streams::ProgressReader;
 use rayon::prelude::*;
 use regex::Regex;
@@ -25,9 +38,18 @@ use serde::{Deserialize, Serialize};
 use triple_accel::levenshtein;
 
 use crate::api;
+use crate::bktree::NameIndex;
+use crate::index;
 use crate::model::*;
+use crate::normalize::{self, Canonicalizer, FoldedIds, Origins};
+use crate::phonetic;
+use crate::query::{self, Query};
+use crate::segment::{self, Segmenter};
+use crate::trigram::{self, TrigramIndex};
+use crate::weighted::NearLetters;
 
 const MAX_CLOSE: usize = 250;
+const MAX_RECENT: usize = 10;
 const DEFAULT_PAGE_SIZE: usize = 20;
 const VERSION: &str = include_str!("../../latest-version.txt");
 const VERSION_URL: &str =
@@ -35,249 +57,916 @@ const VERSION_URL: &str =
 const RELEASE_URL: &str = "https://github.com/benediktwerner/liusearch/releases";
 
 impl Searcher {
-    fn matches(&self, username: &str) -> Option<u32> {
+    pub fn new(pattern: &str, settings: &SearchSettings) -> anyhow::Result<Self> {
+        Self::with_mode(pattern, settings.mode, settings)
+    }
+
+    /// Like [`Searcher::new`] but in `mode` instead of the mode of `settings`.
+    fn with_mode(
+        pattern: &str,
+        mode: SearchMode,
+        settings: &SearchSettings,
+    ) -> anyhow::Result<Self> {
+        let term = pattern;
+        // Regexes are applied to the folded usernames as they are, query patterns are
+        // folded separately according to their mode and words are searched in the names
+        let folded = settings.folding.apply(pattern);
+        let confusables = settings.folding.apply_to_confusables(&settings.confusables);
+        let pattern = match mode {
+            SearchMode::RegEx | SearchMode::Query | SearchMode::Words => pattern,
+            _ => &folded,
+        };
+        let searcher = match mode {
+            SearchMode::Plain => Self::Plain(pattern.to_string()),
+            SearchMode::NumberReplacements => {
+                Self::Regex(Regex::new(&similar_letters(pattern, &confusables))?)
+            }
+            SearchMode::Levenshtein => Self::levenshtein(pattern.to_string(), settings),
+            SearchMode::RegEx => Self::Regex(Regex::new(pattern)?),
+            SearchMode::Terms => Self::Terms(Arc::new(TermList {
+                automaton: AhoCorasick::new(
+                    settings.terms.iter().map(|t| settings.folding.apply(t)),
+                )?,
+                terms: settings.terms.clone(),
+                exceptions: settings
+                    .terms
+                    .iter()
+                    .map(|term| exceptions(term, settings))
+                    .collect::<anyhow::Result<_>>()?,
+            })),
+            SearchMode::SimilarLevenshtein => {
+                let canonicalizer = Canonicalizer::new(&confusables)?;
+                let pattern = canonicalizer.apply(pattern);
+                Self::Canonical(
+                    Arc::new(canonicalizer),
+                    Box::new(Self::levenshtein(pattern, settings)),
+                )
+            }
+            SearchMode::Phonetic => {
+                let code = phonetic::encode(pattern);
+                ensure!(!code.is_empty(), "The pattern has no consonants");
+                // Codes are short and similar sounding names are already equal, so only
+                // codes of five or more sounds allow differences at all
+                let lev = LevenshteinSettings {
+                    max_k: settings
+                        .lev
+                        .max_k_for(code.len())
+                        .min((code.len() as u32 - 1) / 4),
+                    ..settings.lev
+                };
+                Self::Phonetic(Box::new(Self::Levenshtein(code, lev)))
+            }
+            SearchMode::Query => {
+                let query = query::parse(pattern)?;
+                ensure!(
+                    query.is_positive(),
+                    "The query needs a pattern names have to match, not just NOT"
+                );
+                Self::compile(&query, settings)?
+            }
+            SearchMode::Words => {
+                let segmenter = Segmenter::new(&settings.terms);
+                let words: Vec<_> = pattern
+                    .split_whitespace()
+                    .flat_map(|word| segmenter.segment(word))
+                    .collect();
+                ensure!(!words.is_empty(), "The pattern has no words");
+                Self::Words(Arc::new(segmenter), words)
+            }
+            SearchMode::SimilarNames => {
+                bail!("Similar names can't be combined with other searches")
+            }
+        };
+        // Term lists and queries apply the exceptions of their individual terms
+        Ok(match exceptions(term, settings)? {
+            Some(words) if !matches!(mode, SearchMode::Terms | SearchMode::Query) => {
+                Self::Except(Box::new(searcher), words)
+            }
+            _ => searcher,
+        })
+    }
+
+    fn levenshtein(pattern: String, settings: &SearchSettings) -> Self {
+        let lev = LevenshteinSettings {
+            max_k: settings.lev.max_k_for(pattern.len()),
+            ..settings.lev
+        };
+        if lev.weighted {
+            Self::Weighted(
+                pattern,
+                lev,
+                Arc::new(NearLetters::new(&settings.confusables)),
+            )
+        } else {
+            Self::Levenshtein(pattern, lev)
+        }
+    }
+
+    fn compile(query: &Query, settings: &SearchSettings) -> anyhow::Result<Self> {
+        let compile_all = |parts: &[Query]| {
+            parts
+                .iter()
+                .map(|part| Self::compile(part, settings))
+                .collect::<anyhow::Result<_>>()
+        };
+        Ok(match query {
+            Query::Pattern(mode, pattern) => {
+                check_query_pattern(*mode, pattern, settings)?;
+                Self::with_mode(pattern, *mode, settings)?
+            }
+            Query::And(parts) => Self::All(compile_all(parts)?),
+            Query::Or(parts) => Self::Any(compile_all(parts)?),
+            Query::Not(part) => Self::Not(Box::new(Self::compile(part, settings)?)),
+        })
+    }
+
+    /// Searches every term in all [`SWEEP_MODES`]. Levenshtein is skipped for terms so short
+    /// that every username would contain a match.
+    fn sweep(settings: &SearchSettings) -> anyhow::Result<Self> {
+        let mut sweep = Vec::with_capacity(settings.terms.len());
+        for term in &settings.terms {
+            let mut searchers = [None, None, None];
+            for (searcher, mode) in searchers.iter_mut().zip(SWEEP_MODES) {
+                if mode != SearchMode::Levenshtein
+                    || settings.lev.full_name
+                    || term.len() > settings.lev.max_k_for(term.len()) as usize
+                {
+                    *searcher = Some(Self::with_mode(term, mode, settings)?);
+                }
+            }
+            sweep.push(SweepTerm {
+                term: term.clone(),
+                searchers,
+            });
+        }
+        Ok(Self::Sweep(sweep.into()))
+    }
+
+    /// Distance of the match in `username`, the byte ranges that matched and the terms found.
+    #[allow(clippy::single_range_in_vec_init)]
+    pub fn matches(&self, username: &str) -> Option<Hit> {
         use Searcher::*;
         match self {
-            Plain(pattern) => username.contains(pattern).then_some(0),
-            Regex(regex) => regex.is_match(username).then_some(0),
+            Plain(pattern) => found(
+                username
+                    .match_indices(pattern.as_str())
+                    .map(|(i, m)| i..i + m.len())
+                    .collect(),
+            ),
+            Regex(regex) if !regex.is_match(username) => None,
+            Regex(regex) => found(regex.find_iter(username).map(|m| m.range()).collect()),
+            Levenshtein(pattern, lev) if lev.full_name => {
+                levenshtein::levenshtein_simd_k_with_opts(
+                    pattern.as_bytes(),
+                    username.as_bytes(),
+                    lev.max_k,
+                    false,
+                    lev.edit_costs(),
+                )
+                .map(|(k, _)| Hit::new(k, vec![0..username.len()]))
+            }
             Levenshtein(pattern, ..) if username.len() < pattern.len() => None,
             Levenshtein(pattern, lev) => levenshtein::levenshtein_search_simd_with_opts(
                 pattern.as_bytes(),
                 username.as_bytes(),
                 lev.max_k,
-                // ((pattern.len() >> 1) as u32) + ((pattern.len() as u32) & 1),
                 triple_accel::SearchType::Best,
                 lev.edit_costs(),
                 // levenshtein::RDAMERAU_COSTS,
                 false,
             )
             .next()
-            .map(|m| m.k),
+            .map(|m| Hit::new(m.k, vec![m.start..m.end])),
+            Weighted(pattern, lev, _) if !lev.full_name && username.len() < pattern.len() => None,
+            Weighted(pattern, lev, near) => near
+                .distance(pattern.as_bytes(), username.as_bytes(), lev)
+                .map(|(k, range)| Hit::new(k, vec![range])),
+            Terms(list) if !list.automaton.is_match(username) => None,
+            Terms(list) => {
+                let hits = list.hits(username);
+                let mut terms: Vec<_> = hits.iter().map(|&(i, _)| i).collect();
+                terms.sort_unstable();
+                terms.dedup();
+                let mut hit = found(hits.into_iter().map(|(_, r)| r).collect())?;
+                hit.terms = terms
+                    .into_iter()
+                    .map(|i| TermHit {
+                        term: list.terms[i].clone(),
+                        mode: 0,
+                    })
+                    .collect();
+                Some(hit)
+            }
+            Except(searcher, words) => {
+                let found = searcher.matches(username)?;
+                if words.is_match(username) {
+                    let (masked, origins) = mask(words, username);
+                    let hit = searcher.matches(&masked)?;
+                    Some(Hit {
+                        ranges: origins.map(&hit.ranges),
+                        ..hit
+                    })
+                } else {
+                    Some(found)
+                }
+            }
+            Canonical(canonicalizer, searcher) => {
+                let hit = searcher.matches(&canonicalizer.apply(username))?;
+                // Tracking origins for every username would slow down searches
+                Some(Hit {
+                    ranges: canonicalizer.origins(username).map(&hit.ranges),
+                    ..hit
+                })
+            }
+            Phonetic(searcher) => searcher
+                .matches(&phonetic::encode(username))
+                .map(|hit| Hit::new(hit.k, Vec::new())),
+            All(searchers) => searchers
+                .iter()
+                .try_fold(Hit::default(), |mut hit, searcher| {
+                    let part = searcher.matches(username)?;
+                    hit.k = hit.k.max(part.k);
+                    hit.ranges.extend(part.ranges);
+                    hit.terms.extend(part.terms);
+                    Some(hit)
+                }),
+            Any(searchers) => searchers
+                .iter()
+                .filter_map(|searcher| searcher.matches(username))
+                .reduce(|mut hit, part| {
+                    hit.k = hit.k.min(part.k);
+                    hit.ranges.extend(part.ranges);
+                    hit.terms.extend(part.terms);
+                    hit
+                }),
+            Not(searcher) => searcher.matches(username).is_none().then(Hit::default),
+            Words(segmenter, words) => {
+                let lowercase = username.to_ascii_lowercase();
+                if !words.iter().all(|word| lowercase.contains(word.as_str())) {
+                    return None;
+                }
+                let segmented = segmenter.segment(username);
+                let start = segmented
+                    .windows(words.len())
+                    .position(|window| window == words.as_slice())?;
+                let ranges = segment::word_ranges(username, &segmented);
+                Some(Hit::new(
+                    0,
+                    vec![ranges[start].start..ranges[start + words.len() - 1].end],
+                ))
+            }
+            Sweep(terms) => terms
+                .iter()
+                .filter_map(|term| {
+                    let (mode, hit) = term.find(username)?;
+                    Some(Hit {
+                        terms: vec![TermHit {
+                            term: term.term.clone(),
+                            mode,
+                        }],
+                        ..hit
+                    })
+                })
+                .reduce(|mut hit, part| {
+                    hit.k = hit.k.min(part.k);
+                    hit.ranges.extend(part.ranges);
+                    hit.terms.extend(part.terms);
+                    hit
+                }),
+        }
+    }
+
+    /// Literals one of which every match contains, to look up candidates in the trigram index.
+    pub fn literals(&self) -> Option<Vec<String>> {
+        match self {
+            Searcher::Plain(pattern) => Some(vec![pattern.clone()]),
+            Searcher::Regex(regex) => trigram::regex_literals(regex.as_str()),
+            Searcher::Except(searcher, _) => searcher.literals(),
+            _ => None,
+        }
+    }
+
+    /// Whether to match names instead of ids since case matters, e.g. for camelCase words.
+    fn needs_name(&self) -> bool {
+        match self {
+            Searcher::Words(..) => true,
+            Searcher::Except(searcher, _) => searcher.needs_name(),
+            _ => false,
         }
     }
 }
 
+impl TermList {
+    /// Indices of the terms found in `username` outside of their exceptions, with where
+    /// they were found.
+    ///
+    /// A term counts where it doesn't overlap any exception found in `username`, which is
+    /// where it would still be found after masking the exceptions like [`Searcher::Except`].
+    fn hits(&self, username: &str) -> Vec<(usize, Range<usize>)> {
+        self.automaton
+            .find_overlapping_iter(username)
+            .filter(|m| match &self.exceptions[m.pattern().as_usize()] {
+                Some(words) => !words
+                    .find_iter(username)
+                    .any(|e| e.start() < m.end() && m.start() < e.end()),
+                None => true,
+            })
+            .map(|m| (m.pattern().as_usize(), m.range()))
+            .collect()
+    }
+}
+
+/// Rejects query patterns that would match almost every name, like searches for single
+/// patterns need at least three letters.
+fn check_query_pattern(
+    mode: SearchMode,
+    pattern: &str,
+    settings: &SearchSettings,
+) -> anyhow::Result<()> {
+    let len = match mode {
+        SearchMode::RegEx => {
+            let hir = regex_syntax::Parser::new().parse(pattern)?;
+            hir.properties().minimum_len().unwrap_or(usize::MAX)
+        }
+        _ => settings.folding.apply(pattern).len(),
+    };
+    ensure!(
+        len >= 3,
+        "{pattern:?} is too short, patterns need at least 3 letters"
+    );
+    let lev = matches!(
+        mode,
+        SearchMode::Levenshtein | SearchMode::SimilarLevenshtein
+    );
+    ensure!(
+        !lev || settings.lev.full_name || len > settings.lev.max_k_for(len) as usize,
+        "{pattern:?} is too short for the maximum distance"
+    );
+    Ok(())
+}
+
+/// Matches of a search if there are any.
+fn found(ranges: Vec<Range<usize>>) -> Option<Hit> {
+    (!ranges.is_empty()).then(|| Hit::new(0, ranges))
+}
+
+/// Automaton finding the exceptions of `term`, folded like the usernames.
+fn exceptions(term: &str, settings: &SearchSettings) -> anyhow::Result<Option<AhoCorasick>> {
+    let Some(words) = settings.exceptions.get(term) else {
+        return Ok(None);
+    };
+    let automaton = AhoCorasick::builder()
+        .match_kind(MatchKind::LeftmostLongest)
+        .build(words.iter().map(|w| settings.folding.apply(w)))?;
+    Ok(Some(automaton))
+}
+
+/// Replaces every exception in `username` by a space, which never occurs in usernames.
+fn mask(words: &AhoCorasick, username: &str) -> (String, Origins) {
+    normalize::replace_with_origins(words, username, |_| " ")
+}
+
+/// Regex matching `pattern` with any sequence replaced by one of its lookalikes,
+/// trying longer sequences first.
+fn similar_letters(pattern: &str, confusables: &[Confusable]) -> String {
+    let mut confusables: Vec<_> = confusables
+        .iter()
+        .filter(|c| !c.sequence.is_empty())
+        .collect();
+    confusables.sort_by_key(|c| std::cmp::Reverse(c.sequence.len()));
+
+    let mut regex = String::new();
+    let mut rest = pattern;
+    while let Some(c) = rest.chars().next() {
+        match confusables.iter().find(|c| rest.starts_with(&c.sequence)) {
+            Some(confusable) => {
+                let alternatives = std::iter::once(&confusable.sequence)
+                    .chain(&confusable.lookalikes)
+                    .map(|s| regex::escape(s))
+                    .collect::<Vec<_>>();
+                regex += &format!("(?:{})", alternatives.join("|"));
+                rest = &rest[confusable.sequence.len()..];
+            }
+            None => {
+                regex += &regex::escape(&c.to_string());
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+    regex
+}
+
+impl SweepTerm {
+    /// Index into [`SWEEP_MODES`] of the strictest mode matching `username`, with the match.
+    fn find(&self, username: &str) -> Option<(usize, Hit)> {
+        self.searchers
+            .iter()
+            .enumerate()
+            .find_map(|(i, searcher)| Some((i, searcher.as_ref()?.matches(username)?)))
+    }
+}
+
+impl SweepReport {
+    fn new(terms: &[SweepTerm], results: &[Match]) -> Self {
+        let mut groups: Vec<_> = terms
+            .iter()
+            .map(|term| {
+                let mut counts = [0; SWEEP_MODES.len()];
+                let mut matches = Vec::new();
+                for m in results {
+                    if let Some(hit) = m.terms.iter().find(|t| t.term == term.term) {
+                        counts[hit.mode] += 1;
+                        matches.push(m.clone());
+                    }
+                }
+                SweepGroup {
+                    term: term.term.clone(),
+                    counts,
+                    matches,
+                }
+            })
+            .filter(|group| !group.matches.is_empty())
+            .collect();
+        groups.sort_by_key(|group| std::cmp::Reverse(group.matches.len()));
+        Self { groups }
+    }
+}
+
 #[derive(Deserialize, Serialize)]
 #[serde(default)]
 pub struct App {
     page_size: usize,
     password: String,
+    private_key: Option<PathBuf>,
     api_key: String,
     search_mode: SearchMode,
     levenshtein_settings: LevenshteinSettings,
     always_fetch_info: bool,
     hide_closed: bool,
+    show_words: bool,
+    result_view: ResultView,
+    index_cache: bool,
+    encrypt_index: bool,
+    trigram_index: bool,
+    recent_files: Vec<Vec<PathBuf>>,
+    offer_reload: bool,
+    drop_as_results: bool,
+    /// Term list for [`SearchMode::Terms`], lowercase and without duplicates.
+    terms: Vec<String>,
+    confusables: Vec<Confusable>,
+    folding: Folding,
+    exceptions: Exceptions,
     saved_borderline: HashSet<String>,
     saved_obvious: HashSet<String>,
     #[serde(skip)]
     update: Arc<Mutex<Option<String>>>,
     #[serde(skip)]
+    terms_editor: Option<String>,
+    #[serde(skip)]
+    confusables_editor: Option<String>,
+    #[serde(skip)]
+    exceptions_editor: Option<String>,
+    #[serde(skip)]
     state: State,
+    /// The list that was loaded before asking for a password or loading others, to go back to
+    /// if that's cancelled or fails.
+    #[serde(skip)]
+    previous: Option<LoadedState>,
+    /// Splits the names in the results into words, built when they are first shown.
+    #[serde(skip)]
+    segmenter: OnceCell<Segmenter>,
 }
 
 impl App {
     fn load_file(&mut self) {
-        if let Some(path) = rfd::FileDialog::new()
+        if let Some(paths) = rfd::FileDialog::new()
             .add_filter("Usernames", &["txt", "gz", "gpg"])
-            .pick_file()
+            .pick_files()
         {
-            let ext = path.extension().unwrap_or_default();
-            if ext == "gpg" {
-                self.state = State::AskPassword(path);
-            } else {
-                self.load_plain(path);
-            }
+            self.open_files(paths);
         }
     }
 
-    fn do_read(reader: impl BufRead) -> Result<Vec<Username>, std::io::Error> {
-        let mut result = Vec::new();
-        for line in reader.lines() {
-            let name = line?;
-            if !name.is_empty() {
-                result.push(Username {
-                    id: name.to_ascii_lowercase(),
-                    name,
-                });
-            }
+    fn open_files(&mut self, paths: Vec<PathBuf>) {
+        if paths.iter().any(|p| is_encrypted(p)) {
+            self.set_state(State::AskPassword(paths));
+        } else {
+            self.load_files(paths);
         }
-        Ok(result)
     }
 
-    fn load_plain(&mut self, path: PathBuf) {
-        let s = LoadingState::new();
-        self.state = State::Loading(s.clone());
-        std::thread::spawn(move || {
-            let load_file = |path: PathBuf| -> Result<Vec<Username>, std::io::Error> {
-                let compressed = path.extension().filter(|e| *e == "gz").is_some();
-                let file = std::fs::File::open(path)?;
-                let size = file.metadata()?.len() as f32;
-                let mut read = 0;
-                let reader = ProgressReader::new(file, |progress: usize| {
-                    read += progress;
-                    let progress = (read as f32) / size;
-                    s.progress.store(progress.to_bits(), SeqCst);
-                });
-                let reader = BufReader::new(reader);
-                if compressed {
-                    let reader = flate2::bufread::GzDecoder::new(reader);
-                    let reader = BufReader::new(reader);
-                    Self::do_read(reader)
-                } else {
-                    Self::do_read(reader)
+    /// Dropped `.txt` files are shown as results instead if `drop_as_results` is set.
+    fn drop_files(&mut self, paths: Vec<PathBuf>) {
+        match &mut self.state {
+            State::Loading(_) => (),
+            State::Loaded(s) if s.processing.load(SeqCst) => (),
+            State::Loaded(s)
+                if self.drop_as_results
+                    && paths
+                        .iter()
+                        .all(|p| p.extension().is_some_and(|e| e == "txt")) =>
+            {
+                let regex =
+                    Regex::new("^(?:https://lichess.org/@)?/?([a-zA-Z0-9_-]{2,40})(?:$|\\s)")
+                        .unwrap();
+                let mut seen = HashSet::new();
+                let mut results = Vec::new();
+                for path in &paths {
+                    let text = match std::fs::read_to_string(path) {
+                        Ok(text) => text,
+                        Err(error) => {
+                            show_error(&format!("Failed to read {}: {error}", path.display()));
+                            return;
+                        }
+                    };
+                    results.extend(
+                        text.lines()
+                            .filter_map(|l| regex.captures(l.trim()))
+                            .filter_map(|c| c.get(1))
+                            .map(|m| Match::from(m.as_str()))
+                            .filter(|m| seen.insert(m.id.clone())),
+                    );
                 }
-            };
+                s.page = 0;
+                **s.results.lock().unwrap() = results;
+            }
+            _ => self.open_files(paths),
+        }
+    }
 
-            *s.result.lock().unwrap() = Some(load_file(path).map_err(|e| e.to_string()));
-            s.done.store(true, SeqCst);
-        });
+    /// Keeps the loaded list when switching to another state, until loading finishes.
+    fn set_state(&mut self, state: State) {
+        if let State::Loaded(s) = std::mem::replace(&mut self.state, state) {
+            self.previous = Some(s);
+        }
+    }
+
+    /// Goes back to the previously loaded list.
+    fn restore_state(&mut self) {
+        self.state = self
+            .previous
+            .take()
+            .map_or_else(State::default, State::Loaded);
+    }
+
+    fn add_recent(&mut self, paths: Vec<PathBuf>) {
+        self.recent_files.retain(|p| *p != paths);
+        self.recent_files.insert(0, paths);
+        self.recent_files.truncate(MAX_RECENT);
+    }
+
+    fn do_read(
+        reader: impl BufRead,
+        corpus: &mut CorpusBuilder,
+        source: u16,
+        cancel: &AtomicBool,
+    ) -> anyhow::Result<()> {
+        for (i, line) in reader.lines().enumerate() {
+            corpus.push(&line?, source)?;
+            if i & 0xffff == 0 && cancel.load(SeqCst) {
+                return Err(std::io::Error::from(std::io::ErrorKind::Interrupted).into());
+            }
+        }
+        Ok(())
     }
 
-    fn load_encrypted(&mut self, path: PathBuf) {
-        let s = LoadingState::new();
+    fn load_files(&mut self, paths: Vec<PathBuf>) {
+        let s = LoadingState::new(paths.clone());
         let pwd = self.password.clone();
-        self.state = State::Loading(s.clone());
+        let private_key = self.private_key.clone();
+        let encrypted = paths.iter().any(|p| is_encrypted(p));
+        let cache = (self.index_cache
+            && can_cache(encrypted, self.encrypt_index, private_key.is_some(), &pwd))
+        .then(|| index::cache_path(&paths))
+        .flatten();
+        self.set_state(State::Loading(s.clone()));
         std::thread::spawn(move || {
-            let load_file = |path: PathBuf| -> anyhow::Result<Vec<Username>> {
-                let compressed = path.as_os_str().to_str().unwrap().contains(".gz.");
-                let file = std::fs::File::open(path)?;
-                let size = file.metadata()?.len() as f32;
-                let mut read = 0;
-                let reader = ProgressReader::new(file, |progress: usize| {
-                    read += progress;
-                    let divider = if compressed { 4.0 } else { 2.0 };
-                    let progress = (read as f32) / size / divider;
-                    s.progress.store(progress.to_bits(), SeqCst);
-                });
-                let reader = BufReader::new(reader);
-                let msg = pgp::Message::from_bytes(reader)?;
-                let msgs = msg
-                    .decrypt_with_password(|| pwd)?
-                    .collect::<Result<Vec<_>, _>>()?;
-                ensure!(
-                    msgs.len() == 1,
-                    "Expected one PGP message, got {}",
-                    msgs.len()
-                );
-                let msg = msgs.into_iter().next().unwrap().decompress()?;
-
-                if let Some(literal) = msg.get_literal() {
-                    let mut data = literal.data();
-                    let mut v = Vec::new();
-                    if compressed {
-                        let mut read = 0;
-                        let reader = ProgressReader::new(data, |progress: usize| {
-                            read += progress;
-                            let progress = (read as f32) / data.len() as f32 / 4.0 + 0.25;
-                            s.progress.store(progress.to_bits(), SeqCst);
-                        });
-                        let reader = BufReader::new(reader);
-                        flate2::bufread::GzDecoder::new(reader).read_to_end(&mut v)?;
-                        data = &v;
-                    }
-                    let mut read = 0;
-                    let mut result = Vec::new();
-                    let size = data.len() as f32;
-                    for (i, line) in data.lines().enumerate() {
-                        let line = line?;
-                        let name = line.to_string();
-                        if !name.is_empty() {
-                            result.push(Username {
-                                id: name.to_ascii_lowercase(),
-                                name,
-                            });
-                        }
-                        read += line.len();
-                        if i % 100_000 == 0 {
-                            let progress = (read as f32) / size / 2.0 + 0.5;
-                            s.progress.store(progress.to_bits(), SeqCst);
+            let load_files = || -> anyhow::Result<(Corpus, Option<LoadReport>)> {
+                let password = encrypted.then_some(pwd.as_str());
+                if let Some(cache) = &cache {
+                    match index::load(cache, &paths, password) {
+                        Ok(Some((corpus, report))) => return Ok((corpus, Some(report))),
+                        Ok(None) => (),
+                        Err(error) => {
+                            *s.cache_error.lock().unwrap() =
+                                Some(format!("Failed to load index cache: {error:#}"));
                         }
                     }
-                    Ok(result)
-                } else {
-                    bail!("Failed to decrypt message")
                 }
+
+                let key = private_key
+                    .filter(|_| paths.iter().any(|p| is_encrypted(p)))
+                    .map(|path| -> anyhow::Result<SignedSecretKey> {
+                        let file = std::fs::File::open(&path)?;
+                        Ok(SignedSecretKey::from_reader_single(file)?.0)
+                    })
+                    .transpose()
+                    .context("Failed to read private key")?;
+
+                let mut corpus = CorpusBuilder::default();
+                let count = paths.len() as f32;
+                for (i, path) in paths.iter().enumerate() {
+                    let progress = |progress: f32| s.set_progress((i as f32 + progress) / count);
+                    let source = corpus.add_source(path);
+                    if is_encrypted(path) {
+                        Self::load_encrypted(
+                            path,
+                            &pwd,
+                            key.as_ref(),
+                            &mut corpus,
+                            source,
+                            &s.cancel,
+                            progress,
+                        )
+                    } else {
+                        Self::load_plain(path, &mut corpus, source, &s.cancel, progress)
+                    }
+                    .with_context(|| format!("Failed to load {}", path.display()))?;
+                }
+                let (corpus, report) = corpus.finish();
+
+                if let Some(cache) = &cache
+                    && let Err(error) = index::save(cache, &paths, &corpus, &report, password)
+                {
+                    *s.cache_error.lock().unwrap() =
+                        Some(format!("Failed to save index cache: {error:#}"));
+                }
+                Ok((corpus, Some(report)))
             };
 
-            *s.result.lock().unwrap() = Some(load_file(path).map_err(|e| e.to_string()));
+            *s.result.lock().unwrap() = Some(load_files().map_err(|e| format!("{e:#}")));
             s.done.store(true, SeqCst);
         });
     }
 
-    fn do_search(
-        s: LoadedState,
-        mode: SearchMode,
-        lev: LevenshteinSettings,
-        fetch_info: bool,
-        hide_closed: bool,
-    ) {
-        if s.pattern.len() < 3 {
+    fn load_plain(
+        path: &Path,
+        corpus: &mut CorpusBuilder,
+        source: u16,
+        cancel: &AtomicBool,
+        progress: impl Fn(f32),
+    ) -> anyhow::Result<()> {
+        let compressed = path.extension().filter(|e| *e == "gz").is_some();
+        let file = std::fs::File::open(path)?;
+        let size = file.metadata()?.len() as f32;
+        let mut read = 0;
+        let reader = ProgressReader::new(file, |p: usize| {
+            read += p;
+            progress((read as f32) / size);
+        });
+        let reader = BufReader::new(reader);
+        if compressed {
+            let reader = flate2::bufread::GzDecoder::new(reader);
+            let reader = BufReader::new(reader);
+            Self::do_read(reader, corpus, source, cancel)
+        } else {
+            Self::do_read(reader, corpus, source, cancel)
+        }
+    }
+
+    /// Decrypts with `key` if the list is encrypted to it, using `pwd` as the key's passphrase,
+    /// or otherwise with `pwd` as the message password.
+    fn load_encrypted(
+        path: &Path,
+        pwd: &str,
+        key: Option<&SignedSecretKey>,
+        corpus: &mut CorpusBuilder,
+        source: u16,
+        cancel: &AtomicBool,
+        progress: impl Fn(f32) + Send,
+    ) -> anyhow::Result<()> {
+        let compressed = path.as_os_str().to_string_lossy().contains(".gz.");
+        let file = std::fs::File::open(path)?;
+        let size = file.metadata()?.len() as f32;
+        let mut read = 0;
+        let reader = PgpProgressReader::new(file, move |p: usize| {
+            read += p;
+            progress((read as f32) / size);
+        });
+
+        // Decryption, decompression and parsing all happen while streaming through the file,
+        // so the decrypted list is never held in memory as a whole.
+        let (msg, _) = Message::from_reader(BufReader::new(reader))?;
+        let pwd = Password::from(pwd);
+        let ring = TheRing {
+            secret_keys: key.into_iter().collect(),
+            key_passwords: vec![&pwd],
+            message_password: vec![&pwd],
+            ..Default::default()
+        };
+        let (mut msg, _) = msg.decrypt_the_ring(ring, true)?;
+        if msg.is_compressed() {
+            msg = msg.decompress()?;
+        }
+        ensure!(msg.is_literal(), "Failed to decrypt message");
+
+        if compressed {
+            let reader = flate2::bufread::GzDecoder::new(msg);
+            Self::do_read(BufReader::new(reader), corpus, source, cancel)?;
+        } else {
+            Self::do_read(msg, corpus, source, cancel)?;
+        }
+        Ok(())
+    }
+
+    fn do_search(s: LoadedState, settings: SearchSettings, fetch_info: bool, hide_closed: bool) {
+        if settings.mode != SearchMode::Terms && settings.folding.apply(&s.pattern).len() < 3 {
+            if !s.pattern.is_empty() {
+                show_error(&"The pattern is too short, patterns need at least 3 letters");
+            }
             return;
         }
 
         let pattern = s.pattern.to_ascii_lowercase();
-        let searcher = match mode {
-            SearchMode::Plain => Searcher::Plain(pattern),
-            SearchMode::NumberReplacements => {
-                let pattern = pattern
-                    .replace('a', "[a4]")
-                    .replace('e', "[e3]")
-                    .replace('g', "[gq9]")
-                    .replace(['i', 'l'], "[il1]")
-                    .replace('o', "[o0]")
-                    .replace('s', "[s5]")
-                    .replace('u', "[uv]")
-                    .replace('z', "[z2]");
-                match Regex::new(&pattern) {
-                    Ok(regex) => Searcher::Regex(regex),
-                    Err(error) => {
-                        show_error(&error);
-                        return;
-                    }
-                }
-            }
-            SearchMode::Levenshtein => Searcher::Levenshtein(pattern, lev),
-            SearchMode::RegEx => match Regex::new(&pattern) {
-                Ok(regex) => Searcher::Regex(regex),
-                Err(error) => {
-                    show_error(&error);
-                    return;
-                }
-            },
-        };
+        if settings.mode == SearchMode::SimilarNames {
+            let max_k = settings.lev.max_k_for(pattern.len());
+            Self::find_similar_names(s, pattern, max_k, fetch_info, hide_closed);
+            return;
+        }
+        match Searcher::new(&pattern, &settings) {
+            Ok(searcher) => Self::scan(s, searcher, settings.folding, fetch_info, hide_closed),
+            Err(error) => show_error(&error),
+        }
+    }
+
+    fn do_sweep(s: LoadedState, settings: SearchSettings, fetch_info: bool, hide_closed: bool) {
+        match Searcher::sweep(&settings) {
+            Ok(searcher) => Self::scan(s, searcher, settings.folding, fetch_info, hide_closed),
+            Err(error) => show_error(&error),
+        }
+    }
 
+    fn scan(
+        s: LoadedState,
+        searcher: Searcher,
+        folding: Folding,
+        fetch_info: bool,
+        hide_closed: bool,
+    ) {
         s.processing.store(true, SeqCst);
+        s.searching.store(true, SeqCst);
+        s.cancel.store(false, SeqCst);
         s.progress.store(0, SeqCst);
-        s.progress_max.store(s.users.len().max(1), SeqCst);
+        s.progress_max.store(s.corpus.len().max(1), SeqCst);
+
+        // Small enough chunks for results to show up continuously
+        let chunk_size = (s.corpus.len() / 256).max(10_000);
+        let by_name = searcher.needs_name();
+        // The trigram index is built from the ids as they are
+        let literals = if folding.is_noop() && !by_name {
+            searcher.literals()
+        } else {
+            None
+        };
+        let sweep = match &searcher {
+            Searcher::Sweep(terms) => Some(terms.clone()),
+            _ => None,
+        };
 
         std::thread::spawn(move || {
-            s.users
-                .par_chunks(100_000)
-                .for_each_with(searcher, |searcher, users| {
+            let folded = (!folding.is_noop()).then(|| Self::folded_ids(&s, folding));
+            let candidates = literals.and_then(|l| s.trigrams.get()?.candidates(&l));
+            if let Some(candidates) = &candidates {
+                s.progress_max.store(candidates.len().max(1), SeqCst);
+            }
+
+            // Matches not yet merged into the results. Merging copies all results, so it waits
+            // for more new matches as the results grow to only copy each one a few times.
+            let pending = Mutex::new(Vec::new());
+            let scan_chunk =
+                |searcher: &mut Searcher, users: &mut dyn Iterator<Item = (usize, Username)>| {
+                    if s.cancel.load(SeqCst) {
+                        return;
+                    }
                     let mut curr = Vec::new();
-                    for (i, user) in users.iter().enumerate() {
-                        if let Some(k) = searcher.matches(&user.id) {
-                            curr.push(Match::new(user, k));
+                    for (i, (index, user)) in users.enumerate() {
+                        let id = match &folded {
+                            _ if by_name => user.name,
+                            Some(folded) => folded.get(index),
+                            None => user.id,
+                        };
+                        if let Some(hit) = searcher.matches(id) {
+                            let mut m = Match::new(user, hit.k);
+                            m.terms = hit.terms;
+                            m.ranges = match &folded {
+                                Some(_) if !by_name => {
+                                    folding.apply_with_origins(user.id).1.map(&hit.ranges)
+                                }
+                                _ => hit.ranges,
+                            };
+                            curr.push(m);
                         }
                         if i & 0xfff == 0 {
                             s.progress.fetch_add(0xfff, SeqCst);
+                            if s.cancel.load(SeqCst) {
+                                break;
+                            }
                         }
                     }
-                    s.results.lock().unwrap().append(&mut curr);
-                });
-            if mode == SearchMode::Levenshtein {
-                s.results.lock().unwrap().sort_unstable_by_key(|m| m.k);
+                    if curr.is_empty() {
+                        return;
+                    }
+                    let new = {
+                        let mut pending = pending.lock().unwrap();
+                        pending.append(&mut curr);
+                        if pending.len() * 4 < s.results.lock().unwrap().len() {
+                            return;
+                        }
+                        std::mem::take(&mut *pending)
+                    };
+                    merge_sorted(&mut s.results.lock().unwrap(), new, search_order);
+                };
+            match &candidates {
+                Some(candidates) => candidates.par_chunks(chunk_size).for_each_with(
+                    searcher,
+                    |searcher, indices| {
+                        let mut users = indices
+                            .iter()
+                            .map(|&i| (i as usize, s.corpus.get(i as usize)));
+                        scan_chunk(searcher, &mut users);
+                    },
+                ),
+                None => s.corpus.par_chunks(chunk_size).enumerate().for_each_with(
+                    searcher,
+                    |searcher, (chunk, users)| {
+                        let mut users = users
+                            .enumerate()
+                            .map(|(i, user)| (chunk * chunk_size + i, user));
+                        scan_chunk(searcher, &mut users);
+                    },
+                ),
             }
-            if fetch_info {
+            let pending = pending.into_inner().unwrap();
+            merge_sorted(&mut s.results.lock().unwrap(), pending, search_order);
+            s.searching.store(false, SeqCst);
+            if fetch_info && !s.cancel.load(SeqCst) {
+                Self::do_fetch_info_inner(&s, hide_closed);
+            }
+            if let Some(terms) = sweep
+                && !s.cancel.load(SeqCst)
+            {
+                let report = SweepReport::new(&terms, &s.results.lock().unwrap());
+                *s.sweep.lock().unwrap() = Some(Arc::new(report));
+            }
+            s.processing.store(false, SeqCst);
+        });
+    }
+
+    /// Looks up the names within `max_k` edits of the whole pattern,
+    /// building the name index first if this is the first such search. An index whose
+    /// build was cancelled is built again by the next one.
+    fn find_similar_names(
+        s: LoadedState,
+        pattern: String,
+        max_k: u32,
+        fetch_info: bool,
+        hide_closed: bool,
+    ) {
+        s.processing.store(true, SeqCst);
+        s.searching.store(true, SeqCst);
+        s.cancel.store(false, SeqCst);
+        s.progress.store(0, SeqCst);
+        s.progress_max.store(s.corpus.len().max(1), SeqCst);
+
+        std::thread::spawn(move || {
+            let index = match s.names.get() {
+                Some(index) => Some(index),
+                None => NameIndex::new(&s.corpus, &s.progress, &s.cancel)
+                    .map(|index| s.names.get_or_init(|| index)),
+            };
+            let found = index
+                .map(|index| index.find(&s.corpus, &pattern, max_k, &s.cancel))
+                .unwrap_or_default()
+                .into_iter()
+                .map(|(i, k)| Match::new(s.corpus.get(i as usize), k))
+                .collect();
+            merge_sorted(&mut s.results.lock().unwrap(), found, search_order);
+            s.progress.store(s.corpus.len(), SeqCst);
+
+            s.searching.store(false, SeqCst);
+            if fetch_info && !s.cancel.load(SeqCst) {
                 Self::do_fetch_info_inner(&s, hide_closed);
-            } else {
-                s.processing.store(false, SeqCst);
             }
+            s.processing.store(false, SeqCst);
         });
     }
 
+    /// The folded ids of the corpus, reusing them from the last search if possible.
+    fn folded_ids(s: &LoadedState, folding: Folding) -> Arc<FoldedIds> {
+        let mut cached = s.folded.lock().unwrap();
+        match &*cached {
+            Some(folded) if folded.folding == folding => folded.clone(),
+            _ => {
+                let folded = Arc::new(FoldedIds::new(&s.corpus, folding));
+                *cached = Some(folded.clone());
+                folded
+            }
+        }
+    }
+
     fn do_fetch_info(s: LoadedState, hide_closed: bool) {
         s.processing.store(true, SeqCst);
+        s.cancel.store(false, SeqCst);
         std::thread::spawn(move || Self::do_fetch_info_inner(&s, hide_closed));
     }
 
@@ -299,6 +988,9 @@ impl App {
         let results = s.results.clone();
         let mut api_users = HashMap::new();
         for group in names.chunks(300) {
+            if s.cancel.load(SeqCst) {
+                break;
+            }
             match api::fetch_users(group) {
                 Ok(users) => api_users.extend(users.into_iter().map(|u| (u.id.clone(), u))),
                 Err(err) => {
@@ -339,27 +1031,57 @@ impl Default for App {
         Self {
             page_size: DEFAULT_PAGE_SIZE,
             password: String::default(),
+            private_key: None,
             api_key: String::default(),
             levenshtein_settings: LevenshteinSettings::default(),
             search_mode: SearchMode::default(),
             always_fetch_info: false,
             hide_closed: false,
+            show_words: false,
+            result_view: ResultView::default(),
+            index_cache: true,
+            encrypt_index: true,
+            trigram_index: true,
+            recent_files: Vec::new(),
+            offer_reload: true,
+            drop_as_results: false,
+            terms: Vec::new(),
+            confusables: Confusable::defaults(),
+            folding: Folding::default(),
+            exceptions: Exceptions::new(),
             saved_borderline: HashSet::default(),
             saved_obvious: HashSet::default(),
             update: Default::default(),
+            terms_editor: None,
+            confusables_editor: None,
+            exceptions_editor: None,
             state: State::default(),
+            previous: None,
+            segmenter: OnceCell::new(),
         }
     }
 }
 
 impl App {
     pub fn new(cc: &eframe::CreationContext<'_>) -> Self {
-        let app = if let Some(storage) = cc.storage {
+        let mut app: Self = if let Some(storage) = cc.storage {
             eframe::get_value(storage, eframe::APP_KEY).unwrap_or_default()
         } else {
             Self::default()
         };
 
+        if app.offer_reload
+            && let Some(paths) = app.recent_files.first()
+            && paths.iter().all(|p| p.exists())
+            && MessageDialog::new()
+                .set_title("Reload user list")
+                .set_description(&format!("Reload {}?", display_paths(paths)))
+                .set_buttons(MessageButtons::YesNo)
+                .show()
+        {
+            app.open_files(paths.clone());
+        }
+
         let update = app.update.clone();
         std::thread::spawn(move || {
             if let Err(error) = (|| -> anyhow::Result<()> {
@@ -387,39 +1109,104 @@ impl eframe::App for App {
 
         frame.set_window_title(&format!("Lichess User Search - {VERSION}"));
 
+        let dropped = ctx.input(|i| {
+            i.raw
+                .dropped_files
+                .iter()
+                .filter_map(|f| f.path.clone())
+                .filter(|p| is_name_list(p))
+                .collect::<Vec<_>>()
+        });
+        if !dropped.is_empty() {
+            self.drop_files(dropped);
+        }
+        preview_dropped_files(ctx, self.drop_as_results);
+
         egui::CentralPanel::default().show(ctx, |ui| match &mut self.state {
-            AskPassword(path) => {
+            AskPassword(paths) => {
                 let mut decrypt = false;
+                let mut cancel = false;
                 ui.vertical_centered(|ui| {
                     ui.add_space(200.0);
-                    ui.label("Password:");
+                    ui.label(if self.private_key.is_some() {
+                        "Password or key passphrase:"
+                    } else {
+                        "Password:"
+                    });
                     decrypt |= ui
                         .add(TextEdit::singleline(&mut self.password).password(true))
                         .lost_focus()
                         && ui.input(|i| i.key_pressed(Key::Enter));
+                    ui.horizontal(|ui| {
+                        let name = self
+                            .private_key
+                            .as_deref()
+                            .and_then(Path::file_name)
+                            .map_or("None".into(), |n| n.to_string_lossy());
+                        ui.label(format!("Private key: {name}"));
+                        let hint = "Private key for lists encrypted to your public key.\n\
+                                    Lists encrypted with a password can still be decrypted.";
+                        if ui.button("Choose").on_hover_text(hint).clicked()
+                            && let Some(key) = rfd::FileDialog::new()
+                                .add_filter("Private key", &["asc", "gpg", "key", "pgp"])
+                                .pick_file()
+                        {
+                            self.private_key = Some(key);
+                        }
+                        if self.private_key.is_some() && ui.button("Remove").clicked() {
+                            self.private_key = None;
+                        }
+                    });
                     decrypt |= ui.button("Decrypt").clicked();
+                    cancel = ui.button("Cancel").clicked();
                 });
                 if decrypt {
-                    let path = path.clone();
-                    self.load_encrypted(path);
+                    let paths = paths.clone();
+                    self.load_files(paths);
+                } else if cancel {
+                    self.restore_state();
                 }
             }
             Loading(s) => {
                 ctx.request_repaint();
                 let s = s.clone();
                 ui.vertical_centered_justified(|ui| {
-                    ui.add(
-                        ProgressBar::new(f32::from_bits(s.progress.load(SeqCst))).show_percentage(),
-                    );
+                    let cancel = ui
+                        .with_layout(Layout::right_to_left(Align::Center), |ui| {
+                            let cancel = ui.button("Cancel").clicked();
+                            ui.add(
+                                ProgressBar::new(f32::from_bits(s.progress.load(SeqCst)))
+                                    .show_percentage(),
+                            );
+                            cancel
+                        })
+                        .inner;
 
-                    if s.done.load(SeqCst) {
+                    if cancel {
+                        // The loading thread stops on its own and its result is discarded
+                        s.cancel.store(true, SeqCst);
+                        self.restore_state();
+                    } else if s.done.load(SeqCst) {
                         match s.result.lock().unwrap().take() {
-                            Some(Ok(data)) => {
-                                self.state = State::loaded(data);
+                            Some(Ok((corpus, report))) => {
+                                self.add_recent(s.paths.clone());
+                                if let Some(error) = s.cache_error.lock().unwrap().take() {
+                                    show_error(&error);
+                                }
+                                self.state = State::loaded(corpus, report);
+                                self.previous = None;
+                                if self.trigram_index
+                                    && let Loaded(s) = &self.state
+                                {
+                                    let (corpus, trigrams) = (s.corpus.clone(), s.trigrams.clone());
+                                    std::thread::spawn(move || {
+                                        trigrams.get_or_init(|| TrigramIndex::new(&corpus));
+                                    });
+                                }
                             }
                             Some(Err(msg)) => {
                                 show_error(&msg);
-                                self.state = State::default();
+                                self.restore_state();
                             }
                             None => (),
                         }
@@ -429,10 +1216,27 @@ impl eframe::App for App {
             Loaded(s) => {
                 let mut do_load_file = false;
                 let mut do_load_clipboard = false;
+                let mut do_load_recent = None;
 
                 // First taskbar (search controls)
                 ui.horizontal_wrapped(|ui| {
                     do_load_file = ui.button("Load file").clicked();
+                    ui.menu_button("Recent", |ui| {
+                        for paths in &self.recent_files {
+                            if ui.button(display_paths(paths)).clicked() {
+                                do_load_recent = Some(paths.clone());
+                                ui.close_menu();
+                            }
+                        }
+                        if self.recent_files.is_empty() {
+                            ui.label("No recent files");
+                        } else if ui.button("Clear").clicked() {
+                            self.recent_files.clear();
+                            ui.close_menu();
+                        }
+                        ui.separator();
+                        ui.checkbox(&mut self.offer_reload, "Offer to reload on startup");
+                    });
                     do_load_clipboard = ui
                         .button("Load from clipboard")
                         .on_hover_text(
@@ -440,6 +1244,37 @@ impl eframe::App for App {
                              Non-conforming lines and text after the username will be removed.",
                         )
                         .clicked();
+                    ui.checkbox(&mut self.drop_as_results, "Drop .txt as results")
+                        .on_hover_text(
+                            "Show usernames from .txt files dropped onto the window as results \
+                             instead of loading them as the user list",
+                        );
+                    ui.menu_button("Index cache", |ui| {
+                        let hint = "Keep an index of loaded lists to load them faster.\n\
+                                    It is rebuilt when one of the files changes.";
+                        ui.checkbox(&mut self.index_cache, "Cache loaded lists")
+                            .on_hover_text(hint);
+                        ui.add_enabled(
+                            self.index_cache,
+                            egui::Checkbox::new(&mut self.encrypt_index, "Encrypt cache"),
+                        )
+                        .on_hover_text(
+                            "Encrypt the index of encrypted lists with their password.\n\
+                             Encrypted lists are not cached at all without it, nor when \
+                             they are decrypted with a private key or an empty password.",
+                        );
+                        ui.checkbox(&mut self.trigram_index, "Trigram index")
+                            .on_hover_text(
+                                "Index loaded lists in the background to speed up plain and \
+                                 regex searches.\nNeeds about four bytes per letter of all names.",
+                            );
+                        if ui.button("Clear cache").clicked() {
+                            if let Err(error) = index::clear_cache() {
+                                show_error(&error);
+                            }
+                            ui.close_menu();
+                        }
+                    });
                     ui.add_space(20.0);
 
                     ui.label("Results per page:");
@@ -463,16 +1298,68 @@ impl eframe::App for App {
                         "Levenshtein",
                     )
                     .on_hover_text("Search similar patterns based on levenshtein distance");
+                    ui.radio_value(
+                        &mut self.search_mode,
+                        SearchMode::SimilarLevenshtein,
+                        "Similar + Levenshtein",
+                    )
+                    .on_hover_text(
+                        "Replace similar letters/numbers in both pattern and usernames \
+                         before comparing them by levenshtein distance",
+                    );
+                    ui.radio_value(&mut self.search_mode, SearchMode::Phonetic, "Phonetic")
+                        .on_hover_text(
+                            "Search names that sound similar, e.g. phuk when searching for fuck.\n\
+                             The distance is the number of differing sounds.",
+                        );
                     ui.radio_value(&mut self.search_mode, SearchMode::RegEx, "RegEx");
+                    ui.radio_value(&mut self.search_mode, SearchMode::Query, "Query")
+                        .on_hover_text(
+                            "Combine patterns with AND, OR, NOT and parentheses, \
+                             e.g. ass NOT (pass OR bass).\n\
+                             Quote patterns with spaces or parentheses and prefix them \
+                             with plain:, sim:, lev:, simlev:, phon: or re: to choose the mode, \
+                             e.g. lev:bitch or re:\"^a(b|c)\".",
+                        );
+                    ui.radio_value(&mut self.search_mode, SearchMode::Words, "Whole words")
+                        .on_hover_text(
+                            "Search names split into words for whole words, \
+                             e.g. ass matches ass_hat but not passion",
+                        );
+                    ui.radio_value(
+                        &mut self.search_mode,
+                        SearchMode::SimilarNames,
+                        "Similar names",
+                    )
+                    .on_hover_text(
+                        "Search names within the max distance of the whole pattern, \
+                         e.g. to find ban evasion or impersonation accounts.\n\
+                         Every edit costs 1. The first search builds an index, which takes a while.",
+                    );
+                    ui.radio_value(&mut self.search_mode, SearchMode::Terms, "Term list")
+                        .on_hover_text("Search for all terms of a list at once");
 
-                    if let SearchMode::Levenshtein = self.search_mode {
+                    if let SearchMode::NumberReplacements | SearchMode::SimilarLevenshtein =
+                        self.search_mode
+                    {
                         ui.add_space(20.0);
-                        ui.label("Max distance:");
-                        ui.add(
-                            DragValue::new(&mut self.levenshtein_settings.max_k)
-                                .speed(0.2)
-                                .clamp_range(1..=10_u8),
-                        );
+                        if ui.button("Edit letters").clicked() {
+                            self.confusables_editor =
+                                Some(Confusable::format_table(&self.confusables));
+                        }
+                    }
+
+                    if let SearchMode::Levenshtein
+                    | SearchMode::SimilarLevenshtein
+                    | SearchMode::Phonetic = self.search_mode
+                    {
+                        ui.add_space(20.0);
+                        edit_max_distance(ui, &mut self.levenshtein_settings);
+                        ui.checkbox(&mut self.levenshtein_settings.full_name, "Whole name")
+                            .on_hover_text(
+                                "Compare the pattern to whole names \
+                                 instead of the part of them that matches best",
+                            );
                         ui.add_space(20.0);
                         ui.label("Cost:");
                         ui.add(
@@ -499,36 +1386,127 @@ impl eframe::App for App {
                                 .clamp_range(1..=max_cost),
                         )
                         .on_hover_text("Swap cost (cost of swapping two adjacent letters)");
+                        ui.checkbox(&mut self.levenshtein_settings.weighted, "Near letters")
+                            .on_hover_text(
+                                "Substitute neighbouring keys and single look-alike letters \
+                                 from \"Edit letters\" for a lower cost, e.g. fuvk for fuck",
+                            );
+                        if self.levenshtein_settings.weighted {
+                            ui.add(
+                                DragValue::new(&mut self.levenshtein_settings.near_cost)
+                                    .speed(0.2)
+                                    .clamp_range(1..=self.levenshtein_settings.mismatch_cost),
+                            )
+                            .on_hover_text(
+                                "Near cost (cost of replacing a letter with a near one).\n\
+                                 At the mismatch cost, near letters cost half a point less \
+                                 than a mismatch.",
+                            );
+                        }
+                    }
+
+                    if self.search_mode == SearchMode::SimilarNames {
+                        ui.add_space(20.0);
+                        edit_max_distance(ui, &mut self.levenshtein_settings);
+                    }
+
+                    ui.add_space(20.0);
+                    ui.checkbox(&mut self.folding.ignore_separators, "Ignore _ and -")
+                        .on_hover_text("Match e.g. f_u_c_k when searching for fuck");
+                    ui.checkbox(&mut self.folding.collapse_repeats, "Collapse repeats")
+                        .on_hover_text(
+                            "Treat repeated letters like a single one, \
+                             e.g. match fuuuck when searching for fuck",
+                        );
+
+                    if ui
+                        .button("Exceptions")
+                        .on_hover_text(
+                            "Innocent words containing a term, e.g. cucumber for cum.\n\
+                             Names only match a term outside of its exceptions.",
+                        )
+                        .clicked()
+                    {
+                        self.exceptions_editor = Some(format_exceptions(&self.exceptions));
                     }
 
                     ui.add_space(40.0);
                     ui.checkbox(&mut self.always_fetch_info, "Auto-fetch info after search");
                     ui.checkbox(&mut self.hide_closed, "Hide closed accs")
                         .on_hover_text("Only works after fetching additional info");
+                    ui.checkbox(&mut self.show_words, "Show words")
+                        .on_hover_text("Show how names are split into words in the results");
                 });
 
                 ui.separator();
 
                 let results = s.results.clone();
                 let mut results = results.lock().unwrap();
+                let shown = self.result_view.shown(&results);
                 let mut do_search = false;
+                let mut do_sweep = false;
                 let mut do_fetch_info = false;
                 let mut do_close = false;
 
                 // Second taskbar (search input + save lists)
                 ui.add_enabled_ui(!s.processing.load(SeqCst), |ui| {
                     ui.horizontal_wrapped(|ui| {
-                        ui.label("Username: ");
-                        do_search |= ui.text_edit_singleline(&mut s.pattern).lost_focus()
-                            && ui.input(|i| i.key_pressed(Key::Enter));
-                        do_search |= ui
-                            .add_enabled(s.pattern.len() >= 3, Button::new("Search"))
-                            .clicked();
+                        let can_search = if self.search_mode == SearchMode::Terms {
+                            ui.label(format!("{} terms", self.terms.len()));
+                            if ui.button("Edit").clicked() {
+                                self.terms_editor = Some(self.terms.join("\n"));
+                            }
+                            do_sweep = ui
+                                .add_enabled(!self.terms.is_empty(), Button::new("Sweep"))
+                                .on_hover_text(
+                                    "Search every term as is, with similar letters and by \
+                                     levenshtein distance and summarize the matches by term",
+                                )
+                                .clicked();
+                            if s.sweep.lock().unwrap().is_some()
+                                && ui.button("Sweep report").clicked()
+                            {
+                                s.show_sweep = true;
+                            }
+                            !self.terms.is_empty()
+                        } else {
+                            ui.label("Username: ");
+                            do_search |= ui.text_edit_singleline(&mut s.pattern).lost_focus()
+                                && ui.input(|i| i.key_pressed(Key::Enter));
+                            // Like `do_search`, which also checks patterns entered with Enter
+                            self.folding.apply(&s.pattern).len() >= 3
+                        };
+                        do_search |= ui.add_enabled(can_search, Button::new("Search")).clicked();
                         ui.label(format!(
                             "Matches {}/{:}",
                             results.len(),
-                            s.users.len().to_formatted_string(&Locale::en)
-                        ));
+                            s.corpus.len().to_formatted_string(&Locale::en)
+                        ))
+                        .on_hover_text(
+                            s.corpus
+                                .sources
+                                .iter()
+                                .map(|source| {
+                                    format!(
+                                        "{}: {}",
+                                        source.name,
+                                        source.count.to_formatted_string(&Locale::en)
+                                    )
+                                })
+                                .collect::<Vec<_>>()
+                                .join("\n"),
+                        );
+                        if let Some(report) = &s.report
+                            && report.skipped() > 0
+                            && ui
+                                .button(format!(
+                                    "{} lines skipped",
+                                    report.skipped().to_formatted_string(&Locale::en)
+                                ))
+                                .clicked()
+                        {
+                            s.show_report = true;
+                        }
                         ui.add_space(20.0);
                         for (name, coll) in [
                             ("obvious", &mut self.saved_obvious),
@@ -549,7 +1527,7 @@ impl eframe::App for App {
                                 });
                             }
                             if ui.button("Show").clicked() {
-                                *results = coll.iter().map(From::from).collect();
+                                **results = coll.iter().map(From::from).collect();
                             }
                             if ui.button("Clear").clicked() {
                                 coll.clear();
@@ -564,7 +1542,7 @@ impl eframe::App for App {
                             .clicked();
                         let hint = "Close currently shown accounts via the Lichess API \
                                     (requires Admin API key)";
-                        let close_enabled = results.len() < MAX_CLOSE;
+                        let close_enabled = shown.len() < MAX_CLOSE;
                         let close_btn = ui
                             .add_enabled(close_enabled, Button::new("Close accounts"))
                             .on_hover_text(hint)
@@ -593,8 +1571,16 @@ impl eframe::App for App {
                     })
                 });
 
-                if do_search {
-                    if s.users.is_empty() {
+                if do_search || do_sweep {
+                    let settings = SearchSettings {
+                        mode: self.search_mode,
+                        lev: self.levenshtein_settings,
+                        terms: self.terms.clone(),
+                        confusables: self.confusables.clone(),
+                        folding: self.folding,
+                        exceptions: self.exceptions.clone(),
+                    };
+                    if s.corpus.is_empty() {
                         do_load_file = MessageDialog::new()
                             .set_title("Error: No user list loaded")
                             .set_description(
@@ -603,13 +1589,30 @@ impl eframe::App for App {
                             .set_level(MessageLevel::Error)
                             .set_buttons(MessageButtons::YesNo)
                             .show();
+                    } else if do_sweep {
+                        s.page = 0;
+                        results.clear();
+                        *s.sweep.lock().unwrap() = None;
+                        s.show_sweep = true;
+                        s.term = None;
+                        App::do_sweep(
+                            s.clone(),
+                            settings,
+                            self.always_fetch_info,
+                            self.hide_closed,
+                        );
                     } else {
                         s.page = 0;
                         results.clear();
+                        // Similar names aren't searched within names, so exceptions don't apply
+                        s.term = (!matches!(
+                            self.search_mode,
+                            SearchMode::Terms | SearchMode::Query | SearchMode::SimilarNames
+                        ))
+                        .then(|| s.pattern.to_ascii_lowercase());
                         App::do_search(
                             s.clone(),
-                            self.search_mode,
-                            self.levenshtein_settings,
+                            settings,
                             self.always_fetch_info,
                             self.hide_closed,
                         );
@@ -621,49 +1624,58 @@ impl eframe::App for App {
                     return;
                 }
 
+                if let Some(paths) = do_load_recent {
+                    self.open_files(paths);
+                    return;
+                }
+
                 if do_fetch_info {
                     App::do_fetch_info(s.clone(), self.hide_closed);
                 }
 
-                if do_load_clipboard {
-                    if let Some(clip) = get_clipboard() {
-                        let regex = Regex::new(
-                            "^(?:https://lichess.org/@)?/([a-zA-Z0-9_-]{2,40})(?:$|\\s)",
-                        )
-                        .unwrap();
-                        *results = clip
-                            .lines()
-                            .filter_map(|l| regex.captures(l))
-                            .filter_map(|c| c.get(1))
-                            .map(|m| m.as_str())
-                            .map(Match::from)
-                            .collect();
-                    }
+                if do_load_clipboard && let Some(clip) = get_clipboard() {
+                    let regex = Regex::new(
+                        "^(?:https://lichess.org/@)?/([a-zA-Z0-9_-]{2,40})(?:$|\\s)",
+                    )
+                    .unwrap();
+                    **results = clip
+                        .lines()
+                        .filter_map(|l| regex.captures(l))
+                        .filter_map(|c| c.get(1))
+                        .map(|m| m.as_str())
+                        .map(Match::from)
+                        .collect();
                 }
 
                 if do_close
-                    && results.len() < MAX_CLOSE
+                    && shown.len() < MAX_CLOSE
                     && MessageDialog::new()
                         .set_title("Confirm close")
-                        .set_description(&format!("Close {} accounts?", results.len()))
+                        .set_description(&format!("Close {} accounts?", shown.len()))
                         .set_buttons(MessageButtons::OkCancel)
                         .show()
                 {
-                    let names = results
+                    let names = shown
                         .iter()
+                        .map(|&i| &results[i])
                         .filter(|u| u.enabled)
                         .map(|u| &u.name)
                         .cloned()
                         .collect::<Vec<String>>();
                     s.processing.store(true, SeqCst);
+                    s.cancel.store(false, SeqCst);
                     s.progress.store(0, SeqCst);
                     s.progress_max.store(names.len().max(1), SeqCst);
 
                     let progress = s.progress.clone();
                     let processing = s.processing.clone();
+                    let cancel = s.cancel.clone();
                     let api_key = self.api_key.clone();
                     std::thread::spawn(move || {
                         for name in &names {
+                            if cancel.load(SeqCst) {
+                                break;
+                            }
                             if let Err(error) = api::close_account(name, &api_key) {
                                 show_error(&error);
                                 break;
@@ -680,29 +1692,49 @@ impl eframe::App for App {
                 // Progress bar
                 if s.processing.load(SeqCst) {
                     ctx.request_repaint();
-                    ui.add(
-                        ProgressBar::new(
-                            s.progress.load(SeqCst) as f32 / s.progress_max.load(SeqCst) as f32,
-                        )
-                        .show_percentage(),
-                    );
+                    ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+                        let cancelled = s.cancel.load(SeqCst);
+                        if ui.add_enabled(!cancelled, Button::new("Cancel")).clicked() {
+                            s.cancel.store(true, SeqCst);
+                        }
+                        ui.add(
+                            ProgressBar::new(
+                                s.progress.load(SeqCst) as f32 / s.progress_max.load(SeqCst) as f32,
+                            )
+                            .show_percentage(),
+                        );
+                    });
                 } else {
                     ui.add(ProgressBar::new(1.0).text("Done"));
                 }
 
                 ui.separator();
 
-                // Results
-                ui.add_enabled_ui(!s.processing.load(SeqCst), |ui| {
+                // Filters, applied to the results without searching again
+                edit_filters(ui, &mut self.result_view.filters);
+
+                ui.separator();
+
+                // Results, matches can already be triaged while the search is still running
+                let shown = self.result_view.shown(&results);
+                let enabled = !s.processing.load(SeqCst) || s.searching.load(SeqCst);
+                ui.add_enabled_ui(enabled, |ui| {
                     Grid::new("grid")
                         .striped(true)
                         .min_col_width(200.0)
                         .show(ui, |ui| {
+                            let view = &mut self.result_view;
                             ui.strong("");
-                            ui.strong("Username");
-                            ui.strong("Created");
-                            ui.strong("Online");
-                            ui.strong("Games");
+                            sort_header(ui, view, "Username", SortColumn::Username);
+                            sort_header(ui, view, "Created", SortColumn::Created);
+                            sort_header(ui, view, "Online", SortColumn::Online);
+                            sort_header(ui, view, "Games", SortColumn::Games);
+                            ui.strong("Source");
+                            ui.strong("Terms");
+                            if self.show_words {
+                                ui.strong("Words");
+                            }
+                            sort_header(ui, view, "Distance", SortColumn::Distance);
                             ui.end_row();
 
                             let now = Utc::now();
@@ -720,15 +1752,18 @@ impl eframe::App for App {
                             };
 
                             let mut min = s.page * self.page_size;
-                            let mut max = ((s.page + 1) * self.page_size).min(results.len());
+                            let mut max = ((s.page + 1) * self.page_size).min(shown.len());
                             if min > max {
                                 min = 0;
-                                max = self.page_size.min(results.len());
+                                max = self.page_size.min(shown.len());
                                 s.page = 0;
                             }
 
+                            let segmenter = self.show_words.then(|| {
+                                self.segmenter.get_or_init(|| Segmenter::new(&self.terms))
+                            });
                             let mut remove: Option<String> = None;
-                            for user in &results[min..max] {
+                            for user in shown[min..max].iter().map(|&i| &results[i]) {
                                 let obvious = self.saved_obvious.contains(&user.name);
                                 let borderline = self.saved_borderline.contains(&user.name);
                                 let (mut clicked_obv, mut clicked_border) = (false, false);
@@ -750,6 +1785,27 @@ impl eframe::App for App {
                                     {
                                         remove = Some(user.id.clone());
                                     }
+                                    let terms: Vec<_> = if user.terms.is_empty() {
+                                        s.term.iter().cloned().collect()
+                                    } else {
+                                        user.terms.iter().map(|t| t.term.clone()).collect()
+                                    };
+                                    if ui
+                                        .add_enabled(!terms.is_empty(), Button::new("Allow"))
+                                        .on_hover_text(format!(
+                                            "Add this name as an exception for {}",
+                                            terms.join(", ")
+                                        ))
+                                        .clicked()
+                                    {
+                                        for term in terms {
+                                            let words = self.exceptions.entry(term).or_default();
+                                            if !words.contains(&user.id) {
+                                                words.push(user.id.clone());
+                                            }
+                                        }
+                                        remove = Some(user.id.clone());
+                                    }
                                 });
 
                                 if clicked_border || (clicked_obv && obvious) {
@@ -763,18 +1819,30 @@ impl eframe::App for App {
                                 } else if clicked_border && !borderline {
                                     self.saved_borderline.insert(user.name.clone());
                                 }
-                                let mut name = RichText::new(format!(
-                                    "{} {}",
-                                    user.name,
-                                    if obvious || borderline { "⭐" } else { "" }
-                                ));
-                                if !user.enabled {
-                                    name = name.color(Color32::RED).strikethrough();
-                                }
+                                let name = highlight(
+                                    ui,
+                                    &format!(
+                                        "{} {}",
+                                        user.name,
+                                        if obvious || borderline { "⭐" } else { "" }
+                                    ),
+                                    &user.ranges,
+                                    !user.enabled,
+                                );
                                 ui.hyperlink_to(name, format!("https://lichess.org/@/{}", user.id));
                                 ui.label(user.created_at.map(timeago).unwrap_or_default());
                                 ui.label(user.seen_at.map(timeago).unwrap_or_default());
                                 ui.label(user.games.to_string());
+                                ui.label(
+                                    user.source
+                                        .map_or("", |source| s.corpus.source_name(source)),
+                                );
+                                let terms: Vec<_> =
+                                    user.terms.iter().map(|t| t.term.as_str()).collect();
+                                ui.label(terms.join(", "));
+                                if let Some(segmenter) = segmenter {
+                                    ui.label(segmenter.segment(&user.name).join(" "));
+                                }
                                 ui.label(user.k.to_string());
                                 ui.end_row();
                             }
@@ -785,6 +1853,91 @@ impl eframe::App for App {
                         });
                 });
 
+                if let Some(report) = &s.report {
+                    let mut open = s.show_report;
+                    egui::Window::new("Load summary")
+                        .open(&mut open)
+                        .collapsible(false)
+                        .show(ctx, |ui| show_report(ui, report));
+                    s.show_report = open;
+                }
+
+                let sweep = s.sweep.lock().unwrap().clone();
+                if let Some(sweep) = sweep {
+                    let mut open = s.show_sweep;
+                    let mut show = None;
+                    egui::Window::new("Sweep report")
+                        .open(&mut open)
+                        .collapsible(false)
+                        .show(ctx, |ui| show = show_sweep(ui, &sweep));
+                    s.show_sweep = open;
+                    if let Some(group) = show {
+                        **results = match group {
+                            Some(group) => group.matches.clone(),
+                            None => {
+                                let mut seen = HashSet::new();
+                                let mut all: Vec<_> = sweep
+                                    .groups
+                                    .iter()
+                                    .flat_map(|group| &group.matches)
+                                    .filter(|m| seen.insert(&m.id))
+                                    .cloned()
+                                    .collect();
+                                all.sort_by(search_order);
+                                all
+                            }
+                        };
+                        s.page = 0;
+                    }
+                }
+
+                if let Some(text) = &mut self.confusables_editor {
+                    let mut open = true;
+                    let mut apply = false;
+                    egui::Window::new("Similar letters")
+                        .open(&mut open)
+                        .collapsible(false)
+                        .show(ctx, |ui| apply = edit_confusables(ui, text));
+                    if apply {
+                        self.confusables = Confusable::parse_table(text);
+                    }
+                    if apply || !open {
+                        self.confusables_editor = None;
+                    }
+                }
+
+                if let Some(text) = &mut self.exceptions_editor {
+                    let mut open = true;
+                    let mut apply = false;
+                    egui::Window::new("Exceptions")
+                        .open(&mut open)
+                        .collapsible(false)
+                        .show(ctx, |ui| apply = edit_exceptions(ui, text));
+                    if apply {
+                        self.exceptions = parse_exceptions(text);
+                    }
+                    if apply || !open {
+                        self.exceptions_editor = None;
+                    }
+                }
+
+                if let Some(text) = &mut self.terms_editor {
+                    let mut open = true;
+                    let mut apply = false;
+                    egui::Window::new("Term list")
+                        .open(&mut open)
+                        .collapsible(false)
+                        .show(ctx, |ui| apply = edit_terms(ui, text));
+                    if apply {
+                        self.terms = parse_terms(text);
+                        // Terms are words too
+                        self.segmenter.take();
+                    }
+                    if apply || !open {
+                        self.terms_editor = None;
+                    }
+                }
+
                 // Page navigation
                 ui.separator();
                 ui.horizontal(|ui| {
@@ -800,9 +1953,9 @@ impl eframe::App for App {
                     ui.label(format!(
                         "{} / {}",
                         s.page + 1,
-                        (results.len() / self.page_size) + 1
+                        (shown.len() / self.page_size) + 1
                     ));
-                    ui.add_enabled_ui((s.page + 1) * self.page_size < results.len(), |ui| {
+                    ui.add_enabled_ui((s.page + 1) * self.page_size < shown.len(), |ui| {
                         if ui
                             .add_sized(vec2(100.0, 20.0), Button::new("Next"))
                             .clicked()
@@ -824,7 +1977,7 @@ impl eframe::App for App {
                 // Handle scrolling (to move through pages)
                 match ctx.input(|i| i.scroll_delta.y) {
                     y if y > 0.0 && s.page > 0 => s.page -= 1,
-                    y if y < 0.0 && (s.page + 1) * self.page_size < results.len() => s.page += 1,
+                    y if y < 0.0 && (s.page + 1) * self.page_size < shown.len() => s.page += 1,
                     _ => (),
                 }
             }
@@ -832,6 +1985,369 @@ impl eframe::App for App {
     }
 }
 
+/// Like [`ProgressReader`], but `Debug` and `Send` as required by `pgp`.
+struct PgpProgressReader<R, C> {
+    reader: R,
+    callback: C,
+}
+
+impl<R: Read, C: FnMut(usize)> PgpProgressReader<R, C> {
+    const fn new(reader: R, callback: C) -> Self {
+        Self { reader, callback }
+    }
+}
+
+impl<R: Read, C: FnMut(usize)> Read for PgpProgressReader<R, C> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.reader.read(buf)?;
+        (self.callback)(read);
+        Ok(read)
+    }
+}
+
+impl<R, C> std::fmt::Debug for PgpProgressReader<R, C> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PgpProgressReader").finish_non_exhaustive()
+    }
+}
+
+/// Clickable header sorting by `column`, ascending first, then descending, then back to the
+/// order of the search.
+fn sort_header(ui: &mut egui::Ui, view: &mut ResultView, label: &str, column: SortColumn) {
+    let sorted = view.sort == Some(column);
+    let arrow = match (sorted, view.descending) {
+        (false, _) => "",
+        (true, false) => " ⬆",
+        (true, true) => " ⬇",
+    };
+    let header = Button::new(RichText::new(format!("{label}{arrow}")).strong()).frame(false);
+    if ui
+        .add(header)
+        .on_hover_text("Sort by this column")
+        .clicked()
+    {
+        match (sorted, view.descending) {
+            (false, _) => {
+                view.sort = Some(column);
+                view.descending = false;
+            }
+            (true, false) => view.descending = true,
+            (true, true) => view.sort = None,
+        }
+    }
+}
+
+/// Inputs for the result filters, invalid ones are shown in red.
+fn edit_filters(ui: &mut egui::Ui, filters: &mut ResultFilters) {
+    fn parses<T: FromStr>(text: &str) -> bool {
+        text.trim().parse::<T>().is_ok()
+    }
+    fn edit(ui: &mut egui::Ui, text: &mut String, hint: &str, parses: impl Fn(&str) -> bool) {
+        let valid = text.trim().is_empty() || parses(text);
+        let mut edit = TextEdit::singleline(text)
+            .hint_text(hint)
+            .desired_width(80.0);
+        if !valid {
+            edit = edit.text_color(Color32::RED);
+        }
+        ui.add(edit);
+    }
+
+    ui.horizontal_wrapped(|ui| {
+        ui.label("Created after:");
+        edit(
+            ui,
+            &mut filters.created_after,
+            "YYYY-MM-DD",
+            parses::<NaiveDate>,
+        );
+        ui.label("before:");
+        edit(
+            ui,
+            &mut filters.created_before,
+            "YYYY-MM-DD",
+            parses::<NaiveDate>,
+        );
+        ui.add_space(20.0);
+        ui.label("Online within:");
+        edit(ui, &mut filters.seen_within_days, "days", |days| {
+            ResultFilters::seen_since(days, Utc::now()).is_some()
+        });
+        ui.add_space(20.0);
+        ui.label("Games:");
+        edit(ui, &mut filters.min_games, "min", parses::<u32>);
+        ui.label("to");
+        edit(ui, &mut filters.max_games, "max", parses::<u32>);
+        ui.add_space(20.0);
+        ui.checkbox(&mut filters.enabled_only, "Open accounts only");
+        if ui.button("Clear filters").clicked() {
+            *filters = ResultFilters::default();
+        }
+    });
+}
+
+/// `name` with the matched byte `ranges` highlighted, struck through for closed accounts.
+fn highlight(ui: &egui::Ui, name: &str, ranges: &[Range<usize>], closed: bool) -> LayoutJob {
+    let mut format = TextFormat {
+        font_id: TextStyle::Body.resolve(ui.style()),
+        ..Default::default()
+    };
+    if closed {
+        format.color = Color32::RED;
+        format.strikethrough = Stroke::new(1.0, Color32::RED);
+    }
+    let highlighted = TextFormat {
+        background: ui.visuals().selection.bg_fill,
+        ..format.clone()
+    };
+
+    let mut ranges = ranges.to_vec();
+    ranges.sort_unstable_by_key(|r| r.start);
+    let mut job = LayoutJob::default();
+    let mut last = 0;
+    for range in ranges {
+        let start = range.start.max(last);
+        let end = range.end.min(name.len());
+        if start >= end || !name.is_char_boundary(start) || !name.is_char_boundary(end) {
+            continue;
+        }
+        job.append(&name[last..start], 0.0, format.clone());
+        job.append(&name[start..end], 0.0, highlighted.clone());
+        last = end;
+    }
+    job.append(&name[last..], 0.0, format);
+    job
+}
+
+fn display_paths(paths: &[PathBuf]) -> String {
+    paths
+        .iter()
+        .map(|p| p.file_name().unwrap_or(p.as_os_str()).to_string_lossy())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn show_report(ui: &mut egui::Ui, report: &LoadReport) {
+    Grid::new("report").striped(true).show(ui, |ui| {
+        for reason in RejectReason::ALL {
+            ui.label(reason.to_string());
+            ui.label(report.count(reason).to_formatted_string(&Locale::en));
+            ui.end_row();
+        }
+        ui.strong("Skipped lines");
+        ui.strong(report.skipped().to_formatted_string(&Locale::en));
+        ui.end_row();
+    });
+    if report.rejects.len() == MAX_REJECTS {
+        ui.label(format!(
+            "Only the first {} invalid lines can be exported",
+            MAX_REJECTS.to_formatted_string(&Locale::en)
+        ));
+    }
+    if ui
+        .add_enabled(
+            !report.rejects.is_empty(),
+            Button::new("Export invalid lines"),
+        )
+        .clicked()
+        && let Some(path) = rfd::FileDialog::new()
+            .add_filter("Text", &["txt"])
+            .set_file_name("rejected.txt")
+            .save_file()
+    {
+        let text = report
+            .rejects
+            .iter()
+            .map(|(reason, line)| format!("{reason}\t{line}\n"))
+            .collect::<String>();
+        if let Err(error) = std::fs::write(path, text) {
+            show_error(&error);
+        }
+    }
+}
+
+/// Returns the group to show in the results, or `Some(None)` to show all of them.
+fn show_sweep<'a>(ui: &mut egui::Ui, report: &'a SweepReport) -> Option<Option<&'a SweepGroup>> {
+    if report.groups.is_empty() {
+        ui.label("No matches");
+        return None;
+    }
+    let mut show = None;
+    if ui.button("Show all").clicked() {
+        show = Some(None);
+    }
+    egui::ScrollArea::vertical()
+        .max_height(400.0)
+        .show(ui, |ui| {
+            Grid::new("sweep").striped(true).show(ui, |ui| {
+                ui.strong("Term");
+                ui.strong("Plain");
+                ui.strong("Similar letters");
+                ui.strong("Levenshtein");
+                ui.strong("Total");
+                ui.end_row();
+                for group in &report.groups {
+                    ui.label(&group.term);
+                    for count in group.counts {
+                        ui.label(count.to_formatted_string(&Locale::en));
+                    }
+                    if ui
+                        .button(group.matches.len().to_formatted_string(&Locale::en))
+                        .on_hover_text("Show these matches")
+                        .clicked()
+                    {
+                        show = Some(Some(group));
+                    }
+                    ui.end_row();
+                }
+            });
+        });
+    show
+}
+
+/// Editor for the confusables table, returns whether the changes should be applied.
+fn edit_confusables(ui: &mut egui::Ui, text: &mut String) -> bool {
+    ui.label(
+        "One letter or sequence per line, followed by what it may be replaced with,\n\
+         separated by spaces, e.g. \"w vv uu\".",
+    );
+    egui::ScrollArea::vertical()
+        .max_height(400.0)
+        .show(ui, |ui| {
+            ui.add(
+                TextEdit::multiline(text)
+                    .font(TextStyle::Monospace)
+                    .desired_rows(20),
+            );
+        });
+    ui.horizontal(|ui| {
+        if ui.button("Reset to defaults").clicked() {
+            *text = Confusable::format_table(&Confusable::defaults());
+        }
+        ui.button("Apply").clicked()
+    })
+    .inner
+}
+
+/// Editor for the exception lists, returns whether the changes should be applied.
+fn edit_exceptions(ui: &mut egui::Ui, text: &mut String) -> bool {
+    ui.label(
+        "One term per line, followed by innocent words containing it,\n\
+         separated by spaces, e.g. \"cum cucumber document circumstance\".",
+    );
+    egui::ScrollArea::vertical()
+        .max_height(400.0)
+        .show(ui, |ui| {
+            ui.add(
+                TextEdit::multiline(text)
+                    .font(TextStyle::Monospace)
+                    .desired_rows(20),
+            );
+        });
+    ui.button("Apply").clicked()
+}
+
+/// Max distance either in edits or as a percentage of the pattern length.
+fn edit_max_distance(ui: &mut egui::Ui, lev: &mut LevenshteinSettings) {
+    ui.label("Max distance:");
+    if lev.relative {
+        ui.add(
+            DragValue::new(&mut lev.max_percent)
+                .speed(0.5)
+                .clamp_range(1..=100_u8)
+                .suffix("%"),
+        );
+    } else {
+        ui.add(
+            DragValue::new(&mut lev.max_k)
+                .speed(0.2)
+                .clamp_range(1..=10_u8),
+        );
+    }
+    ui.checkbox(&mut lev.relative, "Relative").on_hover_text(
+        "Allow a percentage of the pattern length as distance, rounded down,\n\
+         e.g. 1 for 4 letters and 3 for 12 letters at 25%",
+    );
+}
+
+/// Editor for the term list, returns whether the changes should be applied.
+fn edit_terms(ui: &mut egui::Ui, text: &mut String) -> bool {
+    ui.label("One term per line, matched case-insensitively anywhere in usernames.");
+    egui::ScrollArea::vertical()
+        .max_height(400.0)
+        .show(ui, |ui| {
+            ui.add(TextEdit::multiline(text).desired_rows(20));
+        });
+    ui.horizontal(|ui| {
+        if ui.button("Load from file").clicked()
+            && let Some(path) = rfd::FileDialog::new()
+                .add_filter("Text", &["txt"])
+                .pick_file()
+        {
+            match std::fs::read_to_string(path) {
+                Ok(content) => *text = content,
+                Err(error) => show_error(&error),
+            }
+        }
+        ui.button("Apply").clicked()
+    })
+    .inner
+}
+
+fn parse_terms(text: &str) -> Vec<String> {
+    let mut seen = HashSet::new();
+    text.lines()
+        .map(|line| line.trim().to_ascii_lowercase())
+        .filter(|term| !term.is_empty() && seen.insert(term.clone()))
+        .collect()
+}
+
+fn preview_dropped_files(ctx: &egui::Context, drop_as_results: bool) {
+    let hovered = ctx.input(|i| {
+        i.raw
+            .hovered_files
+            .iter()
+            .filter_map(|f| f.path.as_deref())
+            .filter(|p| is_name_list(p))
+            .count()
+    });
+    if hovered == 0 {
+        return;
+    }
+
+    let text = if drop_as_results {
+        "Drop to load user list\n(.txt files are shown as results)"
+    } else {
+        "Drop to load user list"
+    };
+    let painter = ctx.layer_painter(LayerId::new(Order::Foreground, Id::new("file_drop_target")));
+    let screen_rect = ctx.screen_rect();
+    painter.rect_filled(screen_rect, 0.0, Color32::from_black_alpha(192));
+    painter.text(
+        screen_rect.center(),
+        Align2::CENTER_CENTER,
+        text,
+        TextStyle::Heading.resolve(&ctx.style()),
+        Color32::WHITE,
+    );
+}
+
+fn is_name_list(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext == "txt" || ext == "gz" || ext == "gpg")
+}
+
+/// Whether lists may be cached. Names from encrypted lists are never cached in plain text,
+/// so only encrypted with a non-empty password and not with the passphrase of a private key,
+/// which may well be empty.
+fn can_cache(encrypted: bool, encrypt_index: bool, private_key: bool, password: &str) -> bool {
+    !encrypted || (encrypt_index && !private_key && !password.is_empty())
+}
+
+fn is_encrypted(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "gpg")
+}
+
 fn get_clipboard() -> Option<String> {
     clipboard().and_then(|mut ctx| match ctx.get_contents() {
         Ok(s) => Some(s),
@@ -860,3 +2376,165 @@ fn show_error(msg: &impl Display) {
         .set_level(MessageLevel::Error)
         .show();
 }
+
+#[cfg(test)]
+#[allow(clippy::single_range_in_vec_init)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    fn settings(mode: SearchMode) -> SearchSettings {
+        SearchSettings {
+            mode,
+            lev: LevenshteinSettings::default(),
+            terms: Vec::new(),
+            confusables: Confusable::defaults(),
+            folding: Folding::default(),
+            exceptions: Exceptions::new(),
+        }
+    }
+
+    #[test]
+    fn phonetic() {
+        let searcher = Searcher::new("kunt", &settings(SearchMode::Phonetic)).unwrap();
+        for name in ["cunt99", "xkuntx", "Kkuunt"] {
+            assert!(searcher.matches(name).is_some(), "{name}");
+        }
+        for name in ["want", "hunter", "cute"] {
+            assert!(searcher.matches(name).is_none(), "{name}");
+        }
+        let searcher = Searcher::new("motherfucker", &settings(SearchMode::Phonetic)).unwrap();
+        assert!(searcher.matches("mutherfukr").is_some());
+        assert!(searcher.matches("motherfacker").is_some());
+    }
+
+    #[test]
+    fn encrypted_lists_cache() {
+        assert!(can_cache(false, false, true, ""));
+        assert!(can_cache(true, true, false, "secret"));
+        assert!(!can_cache(true, false, false, "secret"));
+        assert!(!can_cache(true, true, false, ""));
+        assert!(!can_cache(true, true, true, ""));
+        assert!(!can_cache(true, true, true, "passphrase"));
+    }
+
+    #[test]
+    fn similar_names_need_the_index() {
+        assert!(Searcher::new("abc", &settings(SearchMode::SimilarNames)).is_err());
+    }
+
+    #[test]
+    fn query_validation() {
+        let settings = settings(SearchMode::Query);
+        for query in [
+            "lev:abc",
+            "re:.",
+            "re:a.?",
+            "abc ab",
+            "NOT abc",
+            "abc OR NOT def",
+        ] {
+            assert!(Searcher::new(query, &settings).is_err(), "{query}");
+        }
+        let searcher = Searcher::new("ass NOT (pass OR sim:bass) re:a.s", &settings).unwrap();
+        assert!(searcher.matches("xassx").is_some());
+        assert!(searcher.matches("xpassx").is_none());
+        assert!(searcher.matches("x8assx").is_none());
+    }
+
+    #[test]
+    fn folded_confusables() {
+        let folding = Folding {
+            ignore_separators: true,
+            collapse_repeats: true,
+        };
+        for mode in [
+            SearchMode::NumberReplacements,
+            SearchMode::SimilarLevenshtein,
+        ] {
+            let settings = SearchSettings {
+                folding,
+                ..settings(mode)
+            };
+            let searcher = Searcher::new("wank", &settings).unwrap();
+            assert!(searcher.matches(&folding.apply("xvv_ank")).is_some());
+            assert!(searcher.matches(&folding.apply("xvvvank")).is_some());
+        }
+    }
+
+    fn ranges(searcher: &Searcher, username: &str) -> Option<Vec<Range<usize>>> {
+        searcher.matches(username).map(|hit| hit.ranges)
+    }
+
+    #[test]
+    fn match_ranges() {
+        let searcher = Searcher::new("ass", &settings(SearchMode::Plain)).unwrap();
+        assert_eq!(ranges(&searcher, "xassxass"), Some(vec![1..4, 5..8]));
+        let searcher = Searcher::new("as+", &settings(SearchMode::RegEx)).unwrap();
+        assert_eq!(ranges(&searcher, "xassxas"), Some(vec![1..4, 5..7]));
+        assert_eq!(ranges(&searcher, "xsa"), None);
+
+        let settings = SearchSettings {
+            terms: vec!["ass".to_string(), "sex".to_string()],
+            ..settings(SearchMode::Terms)
+        };
+        let hit = Searcher::new("", &settings)
+            .unwrap()
+            .matches("sexass")
+            .unwrap();
+        assert_eq!(hit.ranges, [0..3, 3..6]);
+        let terms: Vec<_> = hit.terms.iter().map(|t| t.term.as_str()).collect();
+        assert_eq!(terms, ["ass", "sex"]);
+    }
+
+    #[test]
+    fn match_origins() {
+        let searcher = Searcher::new("fuck", &settings(SearchMode::SimilarLevenshtein)).unwrap();
+        assert_eq!(ranges(&searcher, "xphuckx"), Some(vec![1..6]));
+
+        let settings = SearchSettings {
+            exceptions: [("ass".to_string(), vec!["class".to_string()])].into(),
+            ..settings(SearchMode::Plain)
+        };
+        let searcher = Searcher::new("ass", &settings).unwrap();
+        assert_eq!(ranges(&searcher, "classyass"), Some(vec![6..9]));
+        assert_eq!(ranges(&searcher, "classy"), None);
+    }
+
+    /// Terms are found outside of their exceptions exactly where they would be found after
+    /// masking the exceptions.
+    #[test]
+    fn term_exceptions_mask() {
+        let terms = ["ab", "ba", "aba"].map(String::from).to_vec();
+        let settings = SearchSettings {
+            terms: terms.clone(),
+            exceptions: [
+                ("ab".to_string(), vec!["bab".to_string(), "aab".to_string()]),
+                ("aba".to_string(), vec!["abab".to_string()]),
+            ]
+            .into(),
+            ..settings(SearchMode::Terms)
+        };
+        let Searcher::Terms(list) = Searcher::new("", &settings).unwrap() else {
+            panic!("Not a term list");
+        };
+        let mut rng = StdRng::seed_from_u64(0x2545_f491_4f6c_dd1d);
+        let mut next = |n: u64| rng.gen_range(0..n) as usize;
+        for _ in 0..5_000 {
+            let username: String = (0..next(10)).map(|_| b"ab"[next(2)] as char).collect();
+            let mut hits: Vec<_> = list.hits(&username).into_iter().map(|(i, _)| i).collect();
+            hits.sort_unstable();
+            hits.dedup();
+            let masked: Vec<_> = (0..terms.len())
+                .filter(|&i| {
+                    let masked = match &list.exceptions[i] {
+                        Some(words) => mask(words, &username).0,
+                        None => username.clone(),
+                    };
+                    masked.contains(terms[i].as_str())
+                })
+                .collect();
+            assert_eq!(hits, masked, "{username}");
+        }
+    }
+}
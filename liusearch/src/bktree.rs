@@ -0,0 +1,213 @@
+//! BK-trees over the ids of a corpus to find all names within a Levenshtein distance of a given
+//! name without comparing it to every other one.
+
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering::SeqCst};
+
+use rayon::prelude::*;
+use triple_accel::levenshtein::levenshtein;
+
+use crate::model::Corpus;
+
+/// One tree per id length, since names of very different lengths can never be close and
+/// smaller trees can be built in parallel.
+pub struct NameIndex {
+    trees: Vec<BkTree>,
+}
+
+/// Every child of a node has a different distance to it. All names within distance `k` of
+/// a query that's at distance `d` of a node are below its children with distances `d - k` to
+/// `d + k`, by the triangle inequality.
+#[derive(Default)]
+struct BkTree {
+    nodes: Vec<Node>,
+}
+
+struct Node {
+    entry: u32,
+    /// Distance to the parent.
+    dist: u32,
+    /// Index of the first child and next sibling, 0 if there is none since the root is
+    /// never a child.
+    first_child: u32,
+    next_sibling: u32,
+}
+
+impl NameIndex {
+    /// Adds the number of inserted entries to `progress` while building, `None` if `cancel`
+    /// was set before it's done.
+    pub fn new(corpus: &Corpus, progress: &AtomicUsize, cancel: &AtomicBool) -> Option<Self> {
+        let mut by_len: Vec<Vec<u32>> = Vec::new();
+        for (i, user) in corpus.iter().enumerate() {
+            let len = user.id.len();
+            if by_len.len() <= len {
+                by_len.resize_with(len + 1, Vec::new);
+            }
+            by_len[len].push(i as u32);
+        }
+        let trees = by_len
+            .into_par_iter()
+            .map(|entries| {
+                let mut tree = BkTree::default();
+                for chunk in entries.chunks(0x1000) {
+                    if cancel.load(SeqCst) {
+                        return None;
+                    }
+                    for &entry in chunk {
+                        tree.insert(corpus, entry);
+                    }
+                    progress.fetch_add(chunk.len(), SeqCst);
+                }
+                Some(tree)
+            })
+            .collect::<Option<_>>()?;
+        Some(Self { trees })
+    }
+
+    /// Entries with ids within distance `max_k` of `id`, with their distance. Only some of
+    /// them if `cancel` is set while looking them up.
+    pub fn find(
+        &self,
+        corpus: &Corpus,
+        id: &str,
+        max_k: u32,
+        cancel: &AtomicBool,
+    ) -> Vec<(u32, u32)> {
+        let min_len = id.len().saturating_sub(max_k as usize);
+        let max_len = id.len() + max_k as usize;
+        self.trees
+            .par_iter()
+            .take(max_len + 1)
+            .skip(min_len)
+            .flat_map_iter(|tree| {
+                let mut found = Vec::new();
+                tree.find(corpus, id, max_k, cancel, &mut found);
+                found
+            })
+            .collect()
+    }
+}
+
+impl BkTree {
+    fn insert(&mut self, corpus: &Corpus, entry: u32) {
+        let node = Node {
+            entry,
+            dist: 0,
+            first_child: 0,
+            next_sibling: 0,
+        };
+        if self.nodes.is_empty() {
+            self.nodes.push(node);
+            return;
+        }
+
+        let id = corpus.get(entry as usize).id.as_bytes();
+        let mut current = 0;
+        loop {
+            let other = corpus.get(self.nodes[current].entry as usize).id.as_bytes();
+            let dist = levenshtein(id, other);
+            let mut child = self.nodes[current].first_child as usize;
+            while child != 0 && self.nodes[child].dist != dist {
+                child = self.nodes[child].next_sibling as usize;
+            }
+            if child != 0 {
+                current = child;
+                continue;
+            }
+
+            let new = self.nodes.len() as u32;
+            self.nodes.push(Node {
+                dist,
+                next_sibling: self.nodes[current].first_child,
+                ..node
+            });
+            self.nodes[current].first_child = new;
+            return;
+        }
+    }
+
+    fn find(
+        &self,
+        corpus: &Corpus,
+        id: &str,
+        max_k: u32,
+        cancel: &AtomicBool,
+        found: &mut Vec<(u32, u32)>,
+    ) {
+        if self.nodes.is_empty() {
+            return;
+        }
+        let mut stack = vec![0];
+        let mut visited = 0_usize;
+        while let Some(current) = stack.pop() {
+            visited += 1;
+            if visited & 0xfff == 0 && cancel.load(SeqCst) {
+                return;
+            }
+            let node: &Node = &self.nodes[current];
+            let other = corpus.get(node.entry as usize).id.as_bytes();
+            let dist = levenshtein(id.as_bytes(), other);
+            if dist <= max_k {
+                found.push((node.entry, dist));
+            }
+            let mut child = node.first_child as usize;
+            while child != 0 {
+                if self.nodes[child].dist.abs_diff(dist) <= max_k {
+                    stack.push(child);
+                }
+                child = self.nodes[child].next_sibling as usize;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::CorpusBuilder;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    fn corpus() -> Corpus {
+        let mut builder = CorpusBuilder::default();
+        let source = builder.add_source(std::path::Path::new("names.txt"));
+        let mut rng = StdRng::seed_from_u64(0x9e37_79b9_7f4a_7c15);
+        let mut next = |n: u64| rng.gen_range(0..n) as usize;
+        for _ in 0..5_000 {
+            let len = 2 + next(8);
+            let name: String = (0..len).map(|_| b"abcde"[next(5)] as char).collect();
+            builder.push(&name, source).unwrap();
+        }
+        builder.finish().0
+    }
+
+    #[test]
+    fn finds_all_close_names() {
+        let corpus = corpus();
+        let cancel = AtomicBool::new(false);
+        let progress = AtomicUsize::new(0);
+        let index = NameIndex::new(&corpus, &progress, &cancel).unwrap();
+        assert_eq!(progress.load(SeqCst), corpus.len());
+
+        for id in ["abc", "abcdeab", "eeeee", "zz"] {
+            for max_k in 0..=3 {
+                let mut found = index.find(&corpus, id, max_k, &cancel);
+                found.sort_unstable();
+                let expected: Vec<_> = corpus
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(i, user)| {
+                        let k = levenshtein(id.as_bytes(), user.id.as_bytes());
+                        (k <= max_k).then_some((i as u32, k))
+                    })
+                    .collect();
+                assert_eq!(found, expected, "{id} {max_k}");
+            }
+        }
+    }
+
+    #[test]
+    fn cancel() {
+        let corpus = corpus();
+        let cancel = AtomicBool::new(true);
+        assert!(NameIndex::new(&corpus, &AtomicUsize::new(0), &cancel).is_none());
+    }
+}
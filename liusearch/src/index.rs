@@ -0,0 +1,376 @@
+use std::{
+    borrow::Cow,
+    fs::File,
+    io::{BufReader, BufWriter, Cursor, Read},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::UNIX_EPOCH,
+};
+
+use anyhow::{bail, ensure};
+use memmap2::Mmap;
+use pgp::{
+    composed::{Message, MessageBuilder},
+    crypto::sym::SymmetricKeyAlgorithm,
+    types::StringToKey,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::model::{Corpus, Entry, IndexBytes, LoadReport, Source, Storage};
+
+/// Index files start with this, encrypted ones are OpenPGP messages that decrypt to it.
+const MAGIC: &[u8; 8] = b"LIUSIDX2";
+const ALIGN: usize = 8;
+
+/// Identifies the files an index was built from. The index is only used if all of them
+/// still have the same size and modification time.
+#[derive(Serialize, Deserialize, PartialEq, Eq)]
+struct Fingerprint {
+    path: PathBuf,
+    len: u64,
+    modified: u128,
+}
+
+impl Fingerprint {
+    fn of(paths: &[PathBuf]) -> anyhow::Result<Vec<Self>> {
+        paths
+            .iter()
+            .map(|path| {
+                let metadata = std::fs::metadata(path)?;
+                Ok(Self {
+                    path: path.canonicalize()?,
+                    len: metadata.len(),
+                    modified: metadata.modified()?.duration_since(UNIX_EPOCH)?.as_nanos(),
+                })
+            })
+            .collect()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Header<'a> {
+    files: Vec<Fingerprint>,
+    sources: Vec<Source>,
+    /// Lines skipped while building the index, to show again whenever it's loaded.
+    report: Cow<'a, LoadReport>,
+    arena_len: usize,
+    entries_len: usize,
+}
+
+pub fn cache_dir() -> Option<PathBuf> {
+    directories_next::ProjectDirs::from("", "", "Lichess User Search")
+        .map(|dirs| dirs.cache_dir().to_path_buf())
+}
+
+/// Location of the index for exactly this selection of files.
+pub fn cache_path(paths: &[PathBuf]) -> Option<PathBuf> {
+    // FNV-1a, since the file name has to stay the same across builds and Rust versions.
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for path in paths {
+        let path = path.canonicalize().ok()?;
+        for &byte in path.as_os_str().as_encoded_bytes().iter().chain(&[0]) {
+            hash = (hash ^ u64::from(byte)).wrapping_mul(0x100_0000_01b3);
+        }
+    }
+    Some(cache_dir()?.join(format!("{hash:016x}.idx")))
+}
+
+pub fn clear_cache() -> std::io::Result<()> {
+    match cache_dir() {
+        Some(dir) if dir.exists() => std::fs::remove_dir_all(dir),
+        _ => Ok(()),
+    }
+}
+
+/// Loads the index at `cache` with its load report if it exists and is still up to date
+/// with `paths`.
+///
+/// Unencrypted indexes are memory-mapped, encrypted ones are decrypted into memory with
+/// `password`. Only indexes that are encrypted if and only if `password` is given are used.
+pub fn load(
+    cache: &Path,
+    paths: &[PathBuf],
+    password: Option<&str>,
+) -> anyhow::Result<Option<(Corpus, LoadReport)>> {
+    if !cache.exists() {
+        return Ok(None);
+    }
+
+    let mut file = File::open(cache)?;
+    let mut magic = [0; MAGIC.len()];
+    file.read_exact(&mut magic)?;
+    if (&magic == MAGIC) == password.is_some() {
+        return Ok(None);
+    }
+    let bytes = Arc::new(if let Some(password) = password {
+        let file = File::open(cache)?;
+        let mut msg =
+            Message::from_bytes(BufReader::new(file))?.decrypt_with_password(&password.into())?;
+        if msg.is_compressed() {
+            msg = msg.decompress()?;
+        }
+        ensure!(msg.is_literal(), "Failed to decrypt index");
+        read_aligned(msg)?
+    } else {
+        // SAFETY: Index files are only ever replaced by renaming a new file over them,
+        // never modified in place, so the mapped contents can't change.
+        IndexBytes::Mapped(unsafe { Mmap::map(&file)? })
+    });
+    let data: &[u8] = &bytes;
+
+    ensure!(data.starts_with(MAGIC), "Not an index file");
+    let header_start = MAGIC.len() + 8;
+    // The lengths come from the file, so they may not even fit in memory.
+    let header_len = read_u64(data, MAGIC.len())?;
+    let Some(header_end) = usize::try_from(header_len)
+        .ok()
+        .and_then(|len| header_start.checked_add(len))
+    else {
+        bail!("Corrupt index file");
+    };
+    let Some(header) = data.get(header_start..header_end) else {
+        bail!("Truncated index file");
+    };
+    let header: Header<'_> = serde_json::from_slice(header)?;
+    if header.files != Fingerprint::of(paths)? {
+        return Ok(None);
+    }
+
+    let arena_start = align(header_end);
+    let layout = (|| {
+        let arena_end = arena_start.checked_add(header.arena_len)?;
+        let entries_start = arena_end.checked_next_multiple_of(ALIGN)?;
+        let entries_len = header
+            .entries_len
+            .checked_mul(std::mem::size_of::<Entry>())?;
+        Some((
+            arena_end,
+            entries_start,
+            entries_start.checked_add(entries_len)?,
+        ))
+    })();
+    let Some((arena_end, entries_start, entries_end)) = layout else {
+        bail!("Corrupt index file");
+    };
+    ensure!(data.len() >= entries_end, "Truncated index file");
+
+    let arena = Storage::Index(bytes.clone(), arena_start..arena_end);
+    let entries = Storage::Index(bytes, entries_start..entries_end);
+    match Corpus::from_storage(arena, entries, header.sources) {
+        Some(corpus) => Ok(Some((corpus, header.report.into_owned()))),
+        None => bail!("Corrupt index file"),
+    }
+}
+
+/// Writes the index for `corpus` built from `paths`, encrypted with `password` if given.
+pub fn save(
+    cache: &Path,
+    paths: &[PathBuf],
+    corpus: &Corpus,
+    report: &LoadReport,
+    password: Option<&str>,
+) -> anyhow::Result<()> {
+    if let Some(dir) = cache.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+
+    let header = serde_json::to_vec(&Header {
+        files: Fingerprint::of(paths)?,
+        sources: corpus.sources.clone(),
+        report: Cow::Borrowed(report),
+        arena_len: corpus.arena().len(),
+        entries_len: corpus.entries().len(),
+    })?;
+
+    // Never write to the index in place since it may currently be mapped.
+    let tmp = cache.with_extension("tmp");
+    let file = File::create(&tmp)?;
+    let mut writer = BufWriter::new(file);
+    let mut reader = index_reader(&header, corpus);
+    if let Some(password) = password {
+        let mut rng = rand::thread_rng();
+        let mut builder = MessageBuilder::from_reader("index", reader)
+            .seipd_v1(&mut rng, SymmetricKeyAlgorithm::AES256);
+        builder.encrypt_with_password(StringToKey::new_default(&mut rng), &password.into())?;
+        builder.to_writer(&mut rng, &mut writer)?;
+    } else {
+        std::io::copy(&mut reader, &mut writer)?;
+    }
+    writer.into_inner()?.sync_all()?;
+    std::fs::rename(tmp, cache)?;
+    Ok(())
+}
+
+/// The serialized index: magic, header length, header, arena and entries, with the latter two
+/// aligned so they can be used directly from a memory map.
+fn index_reader<'a>(header: &'a [u8], corpus: &'a Corpus) -> impl Read + 'a {
+    const PADDING: [u8; ALIGN] = [0; ALIGN];
+    let pad = |len: usize| &PADDING[..align(len) - len];
+
+    let header_end = MAGIC.len() + 8 + header.len();
+    let arena_end = align(header_end) + corpus.arena().len();
+    MAGIC[..]
+        .chain(Cursor::new((header.len() as u64).to_le_bytes()))
+        .chain(header)
+        .chain(pad(header_end))
+        .chain(corpus.arena())
+        .chain(pad(arena_end))
+        .chain(bytemuck::cast_slice(corpus.entries()))
+}
+
+/// Reads all of `reader` into one buffer aligned for the entries, without copying it again.
+fn read_aligned(mut reader: impl Read) -> std::io::Result<IndexBytes> {
+    let mut words = Vec::<u64>::new();
+    let mut len = 0;
+    loop {
+        if len == words.len() * 8 {
+            words.resize((words.len() * 2).max(1 << 16), 0);
+        }
+        match reader.read(&mut bytemuck::cast_slice_mut(&mut words)[len..]) {
+            Ok(0) => break,
+            Ok(n) => len += n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    words.truncate(len.div_ceil(8));
+    Ok(IndexBytes::Decrypted(words, len))
+}
+
+fn read_u64(data: &[u8], at: usize) -> anyhow::Result<u64> {
+    match data.get(at..at + 8) {
+        Some(bytes) => Ok(u64::from_le_bytes(bytes.try_into()?)),
+        None => bail!("Truncated index file"),
+    }
+}
+
+const fn align(len: usize) -> usize {
+    len.div_ceil(ALIGN) * ALIGN
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+    use crate::model::{CorpusBuilder, RejectReason};
+
+    /// A list file and the path of its index in a fresh temporary directory.
+    fn setup(name: &str, names: &[&str]) -> (PathBuf, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("liusearch-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let list = dir.join("names.txt");
+        std::fs::write(&list, names.join("\n")).unwrap();
+        (list, dir.join("index.idx"))
+    }
+
+    fn build(list: &Path) -> (Corpus, LoadReport) {
+        let mut builder = CorpusBuilder::default();
+        let source = builder.add_source(list);
+        for line in std::fs::read_to_string(list).unwrap().lines() {
+            builder.push(line, source).unwrap();
+        }
+        builder.finish()
+    }
+
+    fn names(corpus: &Corpus) -> Vec<(String, String)> {
+        corpus
+            .iter()
+            .map(|u| (u.id.to_string(), u.name.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn round_trip() {
+        let (list, cache) = setup(
+            "round-trip",
+            &["Alice", "bob", "Carol_1", "dave-2", "e", "BOB"],
+        );
+        let paths = [list.clone()];
+        let (corpus, report) = build(&list);
+        save(&cache, &paths, &corpus, &report, None).unwrap();
+
+        let (loaded, loaded_report) = load(&cache, &paths, None).unwrap().unwrap();
+        assert_eq!(names(&loaded), names(&corpus));
+        assert_eq!(loaded.sources[0].count, 4);
+        assert_eq!(loaded_report.counts, report.counts);
+        assert_eq!(loaded_report.count(RejectReason::TooShort), 1);
+        assert_eq!(loaded_report.count(RejectReason::Duplicate), 1);
+        assert_eq!(loaded_report.rejects.len(), 1);
+        // Encrypted and unencrypted indexes are never mixed up
+        assert!(load(&cache, &paths, Some("secret")).unwrap().is_none());
+    }
+
+    #[test]
+    fn encrypted_round_trip() {
+        let (list, cache) = setup("encrypted", &["Alice", "bob"]);
+        let paths = [list.clone()];
+        let (corpus, report) = build(&list);
+        save(&cache, &paths, &corpus, &report, Some("secret")).unwrap();
+
+        assert!(!std::fs::read(&cache).unwrap().starts_with(MAGIC));
+        let (loaded, _) = load(&cache, &paths, Some("secret")).unwrap().unwrap();
+        assert_eq!(names(&loaded), names(&corpus));
+        assert!(load(&cache, &paths, None).unwrap().is_none());
+        assert!(load(&cache, &paths, Some("wrong")).is_err());
+    }
+
+    #[test]
+    fn aligned_reads() {
+        let data: Vec<u8> = (0..200_003_u32).map(|i| i as u8).collect();
+        let bytes = read_aligned(Cursor::new(&data)).unwrap();
+        assert_eq!(&*bytes, data.as_slice());
+        assert!(read_aligned(std::io::empty()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn outdated() {
+        let (list, cache) = setup("outdated", &["Alice", "bob"]);
+        let paths = [list.clone()];
+        let (corpus, report) = build(&list);
+        save(&cache, &paths, &corpus, &report, None).unwrap();
+
+        std::fs::OpenOptions::new()
+            .append(true)
+            .open(&list)
+            .unwrap()
+            .write_all(b"\ncarol")
+            .unwrap();
+        assert!(load(&cache, &paths, None).unwrap().is_none());
+    }
+
+    #[test]
+    fn corrupt() {
+        let (list, cache) = setup("corrupt", &["Alice", "bob"]);
+        let paths = [list.clone()];
+        let (corpus, report) = build(&list);
+        save(&cache, &paths, &corpus, &report, None).unwrap();
+        let data = std::fs::read(&cache).unwrap();
+
+        // Header lengths that would overflow
+        let mut huge = data.clone();
+        huge[MAGIC.len()..MAGIC.len() + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+        std::fs::write(&cache, &huge).unwrap();
+        assert!(load(&cache, &paths, None).is_err());
+
+        std::fs::write(&cache, &data[..data.len() - 1]).unwrap();
+        assert!(load(&cache, &paths, None).is_err());
+
+        // An entry pointing past the end of the arena
+        let mut bad = data;
+        let end = bad.len();
+        bad[end - 12..end - 8].copy_from_slice(&u32::MAX.to_le_bytes());
+        std::fs::write(&cache, &bad).unwrap();
+        assert!(load(&cache, &paths, None).is_err());
+    }
+
+    #[test]
+    fn stable_cache_path() {
+        let (list, _) = setup("path", &[]);
+        let paths = [list.clone()];
+        let path = cache_path(&paths);
+        assert_eq!(path, cache_path(&paths));
+        assert_ne!(path, cache_path(&[list.clone(), list]));
+    }
+}
@@ -0,0 +1,13 @@
+#![allow(clippy::cast_precision_loss)]
+
+pub mod api;
+pub mod app;
+pub mod bktree;
+pub mod index;
+pub mod model;
+pub mod normalize;
+pub mod phonetic;
+pub mod query;
+pub mod segment;
+pub mod trigram;
+pub mod weighted;
@@ -1,13 +1,8 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
-#![allow(clippy::cast_precision_loss)]
 
-use app::App;
+use liusearch::app::App;
 use rfd::{MessageButtons, MessageDialog, MessageLevel};
 
-mod api;
-mod app;
-mod model;
-
 fn setup_panic_hook() {
     let default_panic_hook = std::panic::take_hook();
     std::panic::set_hook(Box::new(move |info| {
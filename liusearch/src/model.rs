@@ -1,41 +1,637 @@
 use std::{
-    path::PathBuf,
+    cmp::Ordering,
+    collections::{hash_map::RandomState, BTreeMap},
+    fmt::Display,
+    hash::BuildHasher,
+    ops::{Deref, DerefMut, Range},
+    path::{Path, PathBuf},
+    str::FromStr,
     sync::{
-        atomic::{AtomicBool, AtomicU32, AtomicUsize},
-        Arc, Mutex,
+        atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering::SeqCst},
+        Arc, Mutex, OnceLock,
     },
 };
 
-use chrono::{DateTime, Utc};
+use aho_corasick::AhoCorasick;
+use anyhow::Context;
+use bytemuck::{Pod, Zeroable};
+use chrono::{DateTime, NaiveDate, Utc};
+use hashbrown::{hash_table, HashTable};
+use memmap2::Mmap;
+use rayon::prelude::*;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 use triple_accel::levenshtein::{self, EditCosts};
 
-pub struct Username {
-    pub id: String,
+use crate::bktree::NameIndex;
+use crate::normalize::{Canonicalizer, FoldedIds};
+use crate::segment::Segmenter;
+use crate::trigram::TrigramIndex;
+use crate::weighted::NearLetters;
+
+#[derive(Clone, Copy)]
+pub struct Username<'a> {
+    pub id: &'a str,
+    pub name: &'a str,
+    pub source: u16,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Source {
     pub name: String,
+    pub count: usize,
+}
+
+/// Location of a single username in the arena of a [`Corpus`].
+///
+/// The lowercase id is only stored separately if it differs from the name,
+/// otherwise `id` and `name` point to the same bytes.
+#[derive(Clone, Copy, Pod, Zeroable)]
+#[repr(C)]
+pub struct Entry {
+    name: u32,
+    id: u32,
+    len: u16,
+    source: u16,
+}
+
+impl Entry {
+    /// Whether the name and id are whole strings within `arena`.
+    fn fits(&self, arena: &str) -> bool {
+        let len = self.len as usize;
+        let fits = |start: u32| arena.get(start as usize..start as usize + len).is_some();
+        fits(self.name) && fits(self.id)
+    }
+}
+
+/// Backing memory of a [`Corpus`], either built in memory or part of a loaded index file.
+pub enum Storage<T> {
+    Owned(Vec<T>),
+    Index(Arc<IndexBytes>, Range<usize>),
+}
+
+/// Contents of an index file, aligned for the [`Entry`] table in it.
+pub enum IndexBytes {
+    Mapped(Mmap),
+    /// Decrypted contents stored in words for their alignment, with the length in bytes.
+    Decrypted(Vec<u64>, usize),
+}
+
+impl Deref for IndexBytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Self::Mapped(map) => map,
+            Self::Decrypted(words, len) => &bytemuck::cast_slice(words)[..*len],
+        }
+    }
+}
+
+impl<T> Default for Storage<T> {
+    fn default() -> Self {
+        Self::Owned(Vec::new())
+    }
+}
+
+impl<T: Pod> Deref for Storage<T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        match self {
+            Self::Owned(v) => v,
+            Self::Index(bytes, range) => bytemuck::cast_slice(&bytes[range.clone()]),
+        }
+    }
+}
+
+/// All loaded usernames, deduplicated by id, together with the files they came from.
+///
+/// The names are stored back to back in one arena to avoid two heap allocations per name.
+#[derive(Default)]
+pub struct Corpus {
+    arena: Storage<u8>,
+    entries: Storage<Entry>,
+    pub sources: Vec<Source>,
+}
+
+impl Corpus {
+    /// Creates a corpus from previously stored parts, checking that all entries point to
+    /// whole strings in the arena.
+    pub fn from_storage(
+        arena: Storage<u8>,
+        entries: Storage<Entry>,
+        sources: Vec<Source>,
+    ) -> Option<Self> {
+        let valid = std::str::from_utf8(&arena).is_ok_and(|text| {
+            entries
+                .iter()
+                .all(|e| e.fits(text) && (e.source as usize) < sources.len())
+        });
+        valid.then_some(Self {
+            arena,
+            entries,
+            sources,
+        })
+    }
+
+    pub fn arena(&self) -> &[u8] {
+        &self.arena
+    }
+
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> Username<'_> {
+        self.user(&self.entries[index])
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Username<'_>> {
+        self.entries.iter().map(|e| self.user(e))
+    }
+
+    pub fn par_chunks(
+        &self,
+        chunk_size: usize,
+    ) -> impl IndexedParallelIterator<Item = impl Iterator<Item = Username<'_>>> {
+        self.entries
+            .par_chunks(chunk_size)
+            .map(move |entries| entries.iter().map(move |e| self.user(e)))
+    }
+
+    pub fn source_name(&self, source: u16) -> &str {
+        self.sources
+            .get(source as usize)
+            .map_or("", |s| s.name.as_str())
+    }
+
+    fn user(&self, entry: &Entry) -> Username<'_> {
+        let slice = |start: u32| {
+            let start = start as usize;
+            let bytes = &self.arena[start..start + entry.len as usize];
+            // Checked in `from_storage` for index files, this only fails if one is modified
+            // while it's mapped
+            std::str::from_utf8(bytes).unwrap_or_default()
+        };
+        Username {
+            id: slice(entry.id),
+            name: slice(entry.name),
+            source: entry.source,
+        }
+    }
 }
 
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum RejectReason {
+    Empty,
+    InvalidCharacters,
+    TooShort,
+    TooLong,
+    Duplicate,
+}
+
+impl RejectReason {
+    pub const ALL: [Self; 5] = [
+        Self::Empty,
+        Self::InvalidCharacters,
+        Self::TooShort,
+        Self::TooLong,
+        Self::Duplicate,
+    ];
+}
+
+impl Display for RejectReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Empty => "Empty",
+            Self::InvalidCharacters => "Invalid characters",
+            Self::TooShort => "Too short",
+            Self::TooLong => "Too long",
+            Self::Duplicate => "Duplicate",
+        })
+    }
+}
+
+/// Lines that were skipped while loading a list.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct LoadReport {
+    pub counts: [usize; RejectReason::ALL.len()],
+    /// Invalid lines (not empty or duplicate ones) for exporting, at most [`MAX_REJECTS`].
+    pub rejects: Vec<(RejectReason, String)>,
+}
+
+impl LoadReport {
+    pub fn count(&self, reason: RejectReason) -> usize {
+        self.counts[reason as usize]
+    }
+
+    pub fn skipped(&self) -> usize {
+        self.counts.iter().sum()
+    }
+
+    fn reject(&mut self, reason: RejectReason, line: &str) {
+        self.counts[reason as usize] += 1;
+        if !matches!(reason, RejectReason::Empty | RejectReason::Duplicate)
+            && self.rejects.len() < MAX_REJECTS
+        {
+            self.rejects.push((reason, line.to_string()));
+        }
+    }
+}
+
+pub const MAX_REJECTS: usize = 100_000;
+
+#[derive(Default)]
+pub struct CorpusBuilder {
+    arena: String,
+    entries: Vec<Entry>,
+    sources: Vec<Source>,
+    ids: HashTable<u32>,
+    hasher: RandomState,
+    id: String,
+    report: LoadReport,
+}
+
+impl CorpusBuilder {
+    pub fn add_source(&mut self, path: &Path) -> u16 {
+        let name = path.file_name().map_or_else(
+            || path.display().to_string(),
+            |n| n.to_string_lossy().into_owned(),
+        );
+        self.sources.push(Source { name, count: 0 });
+        (self.sources.len() - 1) as u16
+    }
+
+    /// Adds the name on this line unless it's not a valid Lichess username or a name with the
+    /// same id was already loaded (from any source). Fails once the names don't fit in 4 GiB.
+    pub fn push(&mut self, line: &str, source: u16) -> anyhow::Result<()> {
+        let name = line.trim_start_matches('\u{feff}').trim();
+        let invalid = if name.is_empty() {
+            Some(RejectReason::Empty)
+        } else if !name
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b'-')
+        {
+            Some(RejectReason::InvalidCharacters)
+        } else if name.len() < 2 {
+            Some(RejectReason::TooShort)
+        } else if name.len() > 30 {
+            Some(RejectReason::TooLong)
+        } else {
+            None
+        };
+        if let Some(reason) = invalid {
+            self.report.reject(reason, line);
+            return Ok(());
+        }
+        let len = name.len() as u16;
+
+        self.id.clear();
+        self.id.push_str(name);
+        self.id.make_ascii_lowercase();
+
+        let Self {
+            arena,
+            entries,
+            sources,
+            ids,
+            hasher,
+            id,
+            report,
+        } = self;
+        let id_of = |i: u32| {
+            let e = &entries[i as usize];
+            &arena[e.id as usize..e.id as usize + e.len as usize]
+        };
+        let hash = hasher.hash_one(id.as_str());
+        let entry = match ids.entry(hash, |&i| id_of(i) == id, |&i| hasher.hash_one(id_of(i))) {
+            hash_table::Entry::Occupied(_) => {
+                report.reject(RejectReason::Duplicate, name);
+                return Ok(());
+            }
+            hash_table::Entry::Vacant(entry) => entry,
+        };
+
+        let added = if id == name {
+            name.len()
+        } else {
+            2 * name.len()
+        };
+        u32::try_from(arena.len() + added).context("The names don't fit in 4 GiB")?;
+        let name_offset = arena.len() as u32;
+        arena.push_str(name);
+        let id_offset = if id == name {
+            name_offset
+        } else {
+            let id_offset = arena.len() as u32;
+            arena.push_str(id);
+            id_offset
+        };
+
+        entry.insert(entries.len() as u32);
+        entries.push(Entry {
+            name: name_offset,
+            id: id_offset,
+            len,
+            source,
+        });
+        sources[source as usize].count += 1;
+
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> (Corpus, LoadReport) {
+        self.arena.shrink_to_fit();
+        self.entries.shrink_to_fit();
+        let corpus = Corpus {
+            arena: Storage::Owned(self.arena.into_bytes()),
+            entries: Storage::Owned(self.entries),
+            sources: self.sources,
+        };
+        (corpus, self.report)
+    }
+}
+
+#[derive(Clone)]
 pub struct Match {
     pub id: String,
     pub name: String,
+    pub source: Option<u16>,
     pub enabled: bool,
     pub created_at: Option<DateTime<Utc>>,
     pub seen_at: Option<DateTime<Utc>>,
     pub games: u32,
     pub k: u32,
+    /// Terms of a multi-pattern search found in the username, in the order of the term list.
+    pub terms: Vec<TermHit>,
+    /// Byte ranges of the username that matched, to highlight them.
+    pub ranges: Vec<Range<usize>>,
 }
 
 impl Match {
-    pub fn new(user: &Username, k: u32) -> Self {
+    pub fn new(user: Username<'_>, k: u32) -> Self {
         Self {
-            id: user.id.clone(),
-            name: user.name.clone(),
+            id: user.id.to_string(),
+            name: user.name.to_string(),
+            source: Some(user.source),
             enabled: true,
             created_at: None,
             seen_at: None,
             games: 0,
             k,
+            terms: Vec::new(),
+            ranges: Vec::new(),
+        }
+    }
+}
+
+/// A term of a multi-pattern search found in a username.
+#[derive(Clone)]
+pub struct TermHit {
+    pub term: String,
+    /// Index into [`SWEEP_MODES`] of the strictest mode that found the term, plain for term
+    /// list searches.
+    pub mode: usize,
+}
+
+/// What a [`Searcher`] found in a username.
+#[derive(Default)]
+pub struct Hit {
+    pub k: u32,
+    /// Byte ranges that matched, empty for phonetic matches since codes can't be mapped back
+    /// to letters.
+    pub ranges: Vec<Range<usize>>,
+    pub terms: Vec<TermHit>,
+}
+
+impl Hit {
+    pub fn new(k: u32, ranges: Vec<Range<usize>>) -> Self {
+        Self {
+            k,
+            ranges,
+            terms: Vec::new(),
+        }
+    }
+}
+
+/// Column to sort results by instead of the order of the search.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum SortColumn {
+    Username,
+    Created,
+    Online,
+    Games,
+    Distance,
+}
+
+impl SortColumn {
+    /// Results without fetched info come last in both directions.
+    fn compare(self, a: &Match, b: &Match, descending: bool) -> Ordering {
+        let fetched = |m: &Match| match self {
+            Self::Created => m.created_at.is_some(),
+            Self::Online => m.seen_at.is_some(),
+            _ => true,
+        };
+        let ordering = match self {
+            Self::Username => a.id.cmp(&b.id),
+            Self::Created => a.created_at.cmp(&b.created_at),
+            Self::Online => a.seen_at.cmp(&b.seen_at),
+            Self::Games => a.games.cmp(&b.games),
+            Self::Distance => a.k.cmp(&b.k),
+        };
+        fetched(b).cmp(&fetched(a)).then(if descending {
+            ordering.reverse()
+        } else {
+            ordering
+        })
+    }
+}
+
+/// Filters on the fetched info of results as typed in, empty or invalid ones are ignored.
+/// Results without fetched info never pass filters on it.
+#[derive(Default, Clone, PartialEq)]
+pub struct ResultFilters {
+    pub created_after: String,
+    pub created_before: String,
+    pub seen_within_days: String,
+    pub min_games: String,
+    pub max_games: String,
+    pub enabled_only: bool,
+}
+
+impl ResultFilters {
+    fn is_empty(&self) -> bool {
+        [
+            &self.created_after,
+            &self.created_before,
+            &self.seen_within_days,
+            &self.min_games,
+            &self.max_games,
+        ]
+        .iter()
+        .all(|text| text.trim().is_empty())
+            && !self.enabled_only
+    }
+
+    /// The time `days` before `now`, or `None` if it's invalid or out of range.
+    pub fn seen_since(days: &str, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let days: u32 = days.trim().parse().ok()?;
+        now.checked_sub_signed(chrono::TimeDelta::try_days(days.into())?)
+    }
+
+    fn matcher(&self, now: DateTime<Utc>) -> impl Fn(&Match) -> bool {
+        fn parse<T: FromStr>(text: &str) -> Option<T> {
+            text.trim().parse().ok()
+        }
+        let created_after: Option<NaiveDate> = parse(&self.created_after);
+        let created_before: Option<NaiveDate> = parse(&self.created_before);
+        let seen_since = Self::seen_since(&self.seen_within_days, now);
+        let min_games: Option<u32> = parse(&self.min_games);
+        let max_games: Option<u32> = parse(&self.max_games);
+        let enabled_only = self.enabled_only;
+        move |m| {
+            let created = m.created_at.map(|t| t.date_naive());
+            created_after.is_none_or(|date| created.is_some_and(|c| c >= date))
+                && created_before.is_none_or(|date| created.is_some_and(|c| c < date))
+                && seen_since.is_none_or(|since| m.seen_at.is_some_and(|t| t >= since))
+                && min_games.is_none_or(|games| m.games >= games)
+                && max_games.is_none_or(|games| m.games <= games)
+                && (!enabled_only || m.enabled)
+        }
+    }
+}
+
+/// How results are shown, they are only sorted and filtered for display.
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct ResultView {
+    pub sort: Option<SortColumn>,
+    pub descending: bool,
+    #[serde(skip)]
+    pub filters: ResultFilters,
+    #[serde(skip)]
+    cache: Option<ShownCache>,
+}
+
+/// Shown results with the results version and view they were computed for.
+struct ShownCache {
+    version: usize,
+    sort: Option<SortColumn>,
+    descending: bool,
+    filters: ResultFilters,
+    shown: Arc<[usize]>,
+}
+
+impl ResultView {
+    /// Indices of the results to show, in the order to show them in. They are only computed
+    /// again when the results or the view changed since the last call.
+    pub fn shown(&mut self, results: &Results) -> Arc<[usize]> {
+        if let Some(cache) = &self.cache
+            && cache.version == results.version
+            && cache.sort == self.sort
+            && cache.descending == self.descending
+            && cache.filters == self.filters
+        {
+            return cache.shown.clone();
+        }
+        let shown: Arc<[usize]> = self.compute(results).into();
+        self.cache = Some(ShownCache {
+            version: results.version,
+            sort: self.sort,
+            descending: self.descending,
+            filters: self.filters.clone(),
+            shown: shown.clone(),
+        });
+        shown
+    }
+
+    fn compute(&self, results: &[Match]) -> Vec<usize> {
+        let mut shown: Vec<usize> = (0..results.len()).collect();
+        if !self.filters.is_empty() {
+            let matcher = self.filters.matcher(Utc::now());
+            shown.retain(|&i| matcher(&results[i]));
+        }
+        if let Some(column) = self.sort {
+            // Stable, so ties stay in search order in both directions
+            shown.sort_by(|&a, &b| column.compare(&results[a], &results[b], self.descending));
+        }
+        shown
+    }
+}
+
+/// Search results with a version that changes whenever they may have been modified, so that
+/// [`ResultView::shown`] can tell when to sort and filter them again.
+pub struct Results {
+    matches: Vec<Match>,
+    version: usize,
+}
+
+impl Results {
+    /// Versions are unique across all results, also when they are replaced by new ones.
+    fn next_version() -> usize {
+        static VERSION: AtomicUsize = AtomicUsize::new(0);
+        VERSION.fetch_add(1, SeqCst)
+    }
+}
+
+impl Default for Results {
+    fn default() -> Self {
+        Self {
+            matches: Vec::new(),
+            version: Self::next_version(),
+        }
+    }
+}
+
+impl Deref for Results {
+    type Target = Vec<Match>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.matches
+    }
+}
+
+impl DerefMut for Results {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.version = Self::next_version();
+        &mut self.matches
+    }
+}
+
+/// Order in which search results are shown: best matches first, then by id.
+pub fn search_order(a: &Match, b: &Match) -> Ordering {
+    a.k.cmp(&b.k).then_with(|| a.id.cmp(&b.id))
+}
+
+/// Merges `new` into the already sorted `results`, keeping them sorted by `cmp`.
+pub fn merge_sorted(
+    results: &mut Vec<Match>,
+    mut new: Vec<Match>,
+    cmp: impl Fn(&Match, &Match) -> Ordering,
+) {
+    new.sort_unstable_by(&cmp);
+    let old = std::mem::take(results);
+    results.reserve(old.len() + new.len());
+    let mut old = old.into_iter().peekable();
+    let mut new = new.into_iter().peekable();
+    loop {
+        let next = match (old.peek(), new.peek()) {
+            (Some(a), Some(b)) if cmp(a, b) == Ordering::Greater => new.next(),
+            (Some(_), _) => old.next(),
+            (None, _) => new.next(),
+        };
+        match next {
+            Some(m) => results.push(m),
+            None => break,
         }
     }
 }
@@ -45,11 +641,14 @@ impl From<&str> for Match {
         Self {
             id: name.to_ascii_lowercase(),
             name: name.to_string(),
+            source: None,
             enabled: true,
             created_at: None,
             seen_at: None,
             games: 0,
             k: 0,
+            terms: Vec::new(),
+            ranges: Vec::new(),
         }
     }
 }
@@ -63,45 +662,81 @@ impl From<&String> for Match {
 #[derive(Clone)]
 #[allow(clippy::type_complexity)]
 pub struct LoadingState {
+    pub paths: Vec<PathBuf>,
     pub progress: Arc<AtomicU32>,
     pub done: Arc<AtomicBool>,
-    pub result: Arc<Mutex<Option<Result<Vec<Username>, String>>>>,
+    pub cancel: Arc<AtomicBool>,
+    pub result: Arc<Mutex<Option<Result<(Corpus, Option<LoadReport>), String>>>>,
+    /// Set if the index cache couldn't be used, which doesn't fail loading.
+    pub cache_error: Arc<Mutex<Option<String>>>,
 }
 
 impl LoadingState {
-    pub fn new() -> Self {
+    pub fn new(paths: Vec<PathBuf>) -> Self {
         Self {
+            paths,
             progress: Arc::new(AtomicU32::new(0.0_f32 as u32)),
             done: Arc::new(AtomicBool::new(false)),
+            cancel: Arc::new(AtomicBool::new(false)),
             result: Arc::new(Mutex::new(None)),
+            cache_error: Arc::new(Mutex::new(None)),
         }
     }
+
+    pub fn set_progress(&self, progress: f32) {
+        self.progress.store(progress.to_bits(), SeqCst);
+    }
 }
 
 #[derive(Clone, Default)]
 pub struct LoadedState {
     pub pattern: String,
-    pub users: Arc<Vec<Username>>,
-    pub results: Arc<Mutex<Vec<Match>>>,
+    pub corpus: Arc<Corpus>,
+    pub report: Option<Arc<LoadReport>>,
+    pub show_report: bool,
+    /// Pattern of the last search if it was for a single one, to add exceptions for.
+    pub term: Option<String>,
+    pub sweep: Arc<Mutex<Option<Arc<SweepReport>>>>,
+    pub folded: Arc<Mutex<Option<Arc<FoldedIds>>>>,
+    /// Built in the background after loading if enabled.
+    pub trigrams: Arc<OnceLock<TrigramIndex>>,
+    /// Built on the first search for similar names.
+    pub names: Arc<OnceLock<NameIndex>>,
+    pub show_sweep: bool,
+    pub results: Arc<Mutex<Results>>,
     pub processing: Arc<AtomicBool>,
+    pub searching: Arc<AtomicBool>,
+    pub cancel: Arc<AtomicBool>,
     pub page: usize,
     pub progress: Arc<AtomicUsize>,
     pub progress_max: Arc<AtomicUsize>,
 }
 
 pub enum State {
-    AskPassword(PathBuf),
+    AskPassword(Vec<PathBuf>),
     Loading(LoadingState),
     Loaded(LoadedState),
 }
 
 impl State {
-    pub fn loaded(users: Vec<Username>) -> Self {
+    pub fn loaded(corpus: Corpus, report: Option<LoadReport>) -> Self {
         Self::Loaded(LoadedState {
             pattern: String::new(),
-            users: Arc::new(users),
+            corpus: Arc::new(corpus),
+            show_report: report.as_ref().is_some_and(|r| {
+                r.skipped() > r.count(RejectReason::Empty) + r.count(RejectReason::Duplicate)
+            }),
+            report: report.map(Arc::new),
+            term: None,
+            sweep: Default::default(),
+            folded: Default::default(),
+            trigrams: Default::default(),
+            names: Default::default(),
+            show_sweep: false,
             results: Default::default(),
             processing: Default::default(),
+            searching: Default::default(),
+            cancel: Default::default(),
             page: 0,
             progress: Arc::new(AtomicUsize::new(0)),
             progress_max: Arc::new(AtomicUsize::new(1)),
@@ -111,52 +746,313 @@ impl State {
 
 impl Default for State {
     fn default() -> Self {
-        Self::loaded(Vec::new())
+        Self::loaded(Corpus::default(), None)
     }
 }
 
-#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
 pub enum SearchMode {
+    #[default]
     Plain,
     NumberReplacements,
     Levenshtein,
     RegEx,
+    Terms,
+    SimilarLevenshtein,
+    Phonetic,
+    Query,
+    Words,
+    SimilarNames,
+}
+
+/// Everything besides the pattern that determines what a search matches.
+#[derive(Clone)]
+pub struct SearchSettings {
+    pub mode: SearchMode,
+    pub lev: LevenshteinSettings,
+    pub terms: Vec<String>,
+    pub confusables: Vec<Confusable>,
+    pub folding: Folding,
+    pub exceptions: Exceptions,
+}
+
+/// Innocent words containing a term, e.g. `cucumber` for `cum`, by term. Names only match a
+/// term if they still match with all of its exceptions removed.
+pub type Exceptions = BTreeMap<String, Vec<String>>;
+
+/// Parses one term per line followed by its exceptions, separated by spaces.
+pub fn parse_exceptions(text: &str) -> Exceptions {
+    text.lines()
+        .filter_map(|line| {
+            let mut words = line.split_whitespace().map(str::to_lowercase);
+            let term = words.next()?;
+            let exceptions: Vec<_> = words.collect();
+            (!exceptions.is_empty()).then_some((term, exceptions))
+        })
+        .collect()
+}
+
+pub fn format_exceptions(exceptions: &Exceptions) -> String {
+    exceptions
+        .iter()
+        .map(|(term, words)| format!("{term} {}\n", words.join(" ")))
+        .collect()
+}
+
+/// What to ignore in usernames and patterns before matching them.
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Folding {
+    /// Ignore `_` and `-`.
+    pub ignore_separators: bool,
+    /// Treat runs of the same character like a single one.
+    pub collapse_repeats: bool,
+}
+
+/// A letter or sequence of letters and what it may be replaced with in "Similar letters" searches.
+#[derive(Deserialize, Serialize, Clone)]
+pub struct Confusable {
+    pub sequence: String,
+    pub lookalikes: Vec<String>,
+}
+
+impl Confusable {
+    pub fn defaults() -> Vec<Self> {
+        [
+            ("a", &["4"][..]),
+            ("b", &["8"]),
+            ("e", &["3"]),
+            ("f", &["ph"]),
+            ("g", &["q", "9"]),
+            ("i", &["l", "1", "|"]),
+            ("l", &["i", "1", "|"]),
+            ("o", &["0"]),
+            ("s", &["5"]),
+            ("t", &["7"]),
+            ("u", &["v"]),
+            ("w", &["vv", "uu"]),
+            ("z", &["2"]),
+        ]
+        .into_iter()
+        .map(|(sequence, lookalikes)| Self {
+            sequence: sequence.to_string(),
+            lookalikes: lookalikes.iter().map(|l| l.to_string()).collect(),
+        })
+        .collect()
+    }
+
+    /// Parses one rule per line: the sequence followed by its lookalikes, separated by spaces.
+    pub fn parse_table(text: &str) -> Vec<Self> {
+        text.lines()
+            .filter_map(|line| {
+                let mut words = line.split_whitespace().map(str::to_lowercase);
+                let sequence = words.next()?;
+                let lookalikes: Vec<_> = words.filter(|w| *w != sequence).collect();
+                (!lookalikes.is_empty()).then_some(Self {
+                    sequence,
+                    lookalikes,
+                })
+            })
+            .collect()
+    }
+
+    pub fn format_table(table: &[Self]) -> String {
+        table
+            .iter()
+            .map(|c| format!("{} {}\n", c.sequence, c.lookalikes.join(" ")))
+            .collect()
+    }
 }
 
 #[derive(Clone)]
 pub enum Searcher {
     Plain(String),
     Regex(Regex),
+    /// The max distance is already resolved for the pattern, see [`LevenshteinSettings::max_k_for`].
     Levenshtein(String, LevenshteinSettings),
+    /// Like [`Searcher::Levenshtein`] with cheaper substitutions of near letters.
+    Weighted(String, LevenshteinSettings, Arc<NearLetters>),
+    Terms(Arc<TermList>),
+    /// Matches names only if they still match with the exceptions removed, see [`Exceptions`].
+    Except(Box<Searcher>, AhoCorasick),
+    /// Matches the canonical form of usernames instead, see [`Canonicalizer`].
+    Canonical(Arc<Canonicalizer>, Box<Searcher>),
+    /// Matches the phonetic code of usernames instead, see [`phonetic::encode`](crate::phonetic::encode).
+    Phonetic(Box<Searcher>),
+    /// Parts of a [`Query`](crate::query::Query), the distance is the largest of all parts.
+    All(Vec<Searcher>),
+    /// The distance is the smallest of all matching parts.
+    Any(Vec<Searcher>),
+    Not(Box<Searcher>),
+    /// Consecutive words of the segmented username, see [`Segmenter`].
+    Words(Arc<Segmenter>, Vec<String>),
+    /// Every term of a list in several search modes, see [`SWEEP_MODES`].
+    Sweep(Arc<[SweepTerm]>),
 }
 
-impl Default for SearchMode {
-    fn default() -> Self {
-        Self::Plain
-    }
+pub struct TermList {
+    pub automaton: AhoCorasick,
+    pub terms: Vec<String>,
+    /// Exceptions of each term, if it has any.
+    pub exceptions: Vec<Option<AhoCorasick>>,
+}
+
+/// Modes a sweep searches each term in, from the most to the least strict.
+pub const SWEEP_MODES: [SearchMode; 3] = [
+    SearchMode::Plain,
+    SearchMode::NumberReplacements,
+    SearchMode::Levenshtein,
+];
+
+pub struct SweepTerm {
+    pub term: String,
+    /// One searcher per entry of [`SWEEP_MODES`], `None` if the mode is skipped for this term.
+    pub searchers: [Option<Searcher>; SWEEP_MODES.len()],
+}
+
+pub struct SweepReport {
+    /// Terms with at least one match, the most frequent first.
+    pub groups: Vec<SweepGroup>,
+}
+
+pub struct SweepGroup {
+    pub term: String,
+    /// Matches per entry of [`SWEEP_MODES`], each counted in the strictest mode that found it.
+    pub counts: [usize; SWEEP_MODES.len()],
+    pub matches: Vec<Match>,
 }
 
 #[derive(Deserialize, Serialize, Clone, Copy)]
+#[serde(default)]
 pub struct LevenshteinSettings {
     pub max_k: u32,
+    /// Use `max_percent` of the pattern length as max distance instead of `max_k`.
+    pub relative: bool,
+    pub max_percent: u8,
+    /// Compare whole names to the pattern instead of their best matching part.
+    pub full_name: bool,
     pub mismatch_cost: u8,
     pub gap_cost: u8,
     pub swap_cost: u8,
+    /// Substitute neighbouring keys and look-alike letters for `near_cost`, see [`NearLetters`].
+    pub weighted: bool,
+    pub near_cost: u8,
 }
 
 impl LevenshteinSettings {
     pub fn edit_costs(self) -> EditCosts {
         levenshtein::EditCosts::new(self.mismatch_cost, self.gap_cost, 0, Some(self.swap_cost))
     }
+
+    /// The max distance for a pattern of length `len`, rounded down for relative ones.
+    pub fn max_k_for(self, len: usize) -> u32 {
+        if self.relative {
+            (len * self.max_percent as usize / 100) as u32
+        } else {
+            self.max_k
+        }
+    }
 }
 
 impl Default for LevenshteinSettings {
     fn default() -> Self {
         Self {
             max_k: 3,
+            relative: false,
+            max_percent: 25,
+            full_name: false,
             mismatch_cost: 1,
             gap_cost: 1,
             swap_cost: 1,
+            weighted: false,
+            near_cost: 1,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(names: &[(&str, u32)]) -> Vec<Match> {
+        names
+            .iter()
+            .map(|&(name, k)| Match {
+                k,
+                ..Match::from(name)
+            })
+            .collect()
+    }
+
+    fn ids(results: &[Match]) -> Vec<(&str, u32)> {
+        results.iter().map(|m| (m.id.as_str(), m.k)).collect()
+    }
+
+    #[test]
+    fn merge_sorted_keeps_order() {
+        let mut results = Vec::new();
+        merge_sorted(&mut results, matches(&[("bb", 1), ("aa", 0)]), search_order);
+        assert_eq!(ids(&results), [("aa", 0), ("bb", 1)]);
+
+        merge_sorted(
+            &mut results,
+            matches(&[("cc", 0), ("ab", 1), ("zz", 2)]),
+            search_order,
+        );
+        assert_eq!(
+            ids(&results),
+            [("aa", 0), ("cc", 0), ("ab", 1), ("bb", 1), ("zz", 2)]
+        );
+
+        merge_sorted(&mut results, Vec::new(), search_order);
+        assert_eq!(results.len(), 5);
+    }
+
+    #[test]
+    fn unfetched_sort_last() {
+        let mut results = Results::default();
+        results.extend(matches(&[("aa", 0), ("bb", 0), ("cc", 0)]));
+        let day = |d| {
+            NaiveDate::from_ymd_opt(2020, 1, d)
+                .unwrap()
+                .and_hms_opt(0, 0, 0)
+        };
+        results[1].created_at = day(2).map(|t| t.and_utc());
+        results[2].created_at = day(1).map(|t| t.and_utc());
+        let mut view = ResultView {
+            sort: Some(SortColumn::Created),
+            ..ResultView::default()
+        };
+        assert_eq!(*view.shown(&results), [2, 1, 0]);
+        view.descending = true;
+        assert_eq!(*view.shown(&results), [1, 2, 0]);
+    }
+
+    #[test]
+    fn shown_cache() {
+        let mut results = Results::default();
+        results.extend(matches(&[("aa", 0), ("bb", 0), ("cc", 0)]));
+        let mut view = ResultView::default();
+        let shown = view.shown(&results);
+        assert!(Arc::ptr_eq(&shown, &view.shown(&results)));
+        results[2].games = 10;
+        assert!(!Arc::ptr_eq(&shown, &view.shown(&results)));
+        view.filters.min_games = "5".to_string();
+        assert_eq!(*view.shown(&results), [2]);
+        // Other results never reuse the cached indices
+        let mut other = Results::default();
+        other.extend(matches(&[("dd", 10)]));
+        assert!(view.shown(&other).is_empty());
+    }
+
+    #[test]
+    fn seen_since() {
+        let now = Utc::now();
+        assert_eq!(
+            ResultFilters::seen_since(" 2 ", now),
+            Some(now - chrono::TimeDelta::days(2))
+        );
+        for days in ["-1", "1.5", "", "4294967295", "99999999999"] {
+            assert_eq!(ResultFilters::seen_since(days, now), None, "{days}");
         }
     }
 }
@@ -0,0 +1,315 @@
+use std::ops::Range;
+
+use aho_corasick::{AhoCorasick, BuildError, MatchKind};
+use rayon::prelude::*;
+
+use crate::model::{Confusable, Corpus, Folding};
+
+/// Replaces everything in the confusables table with a canonical form, so that e.g.
+/// `n1gg3r` and `nigger` normalize to the same string.
+///
+/// Sequences that are lookalikes of each other, directly or through other entries, form a
+/// class and are all replaced by its shortest member.
+pub struct Canonicalizer {
+    automaton: AhoCorasick,
+    canonical: Vec<String>,
+}
+
+impl Canonicalizer {
+    pub fn new(confusables: &[Confusable]) -> Result<Self, BuildError> {
+        let mut classes: Vec<Vec<&str>> = Vec::new();
+        for confusable in confusables {
+            let members: Vec<&str> = std::iter::once(&confusable.sequence)
+                .chain(&confusable.lookalikes)
+                .map(String::as_str)
+                .filter(|s| !s.is_empty())
+                .collect();
+            // Merge all classes sharing a member with this entry, keeping earlier members first
+            let mut class = Vec::new();
+            let mut i = 0;
+            while i < classes.len() {
+                if classes[i].iter().any(|s| members.contains(s)) {
+                    class.extend(classes.remove(i));
+                } else {
+                    i += 1;
+                }
+            }
+            for member in members {
+                if !class.contains(&member) {
+                    class.push(member);
+                }
+            }
+            classes.push(class);
+        }
+
+        let mut patterns = Vec::new();
+        let mut canonical = Vec::new();
+        for class in &classes {
+            // The first member on ties, which is the sequence of the earliest entry
+            let Some(&target) = class.iter().min_by_key(|s| s.len()) else {
+                continue;
+            };
+            for &member in class {
+                if member != target {
+                    patterns.push(member);
+                    canonical.push(target.to_string());
+                }
+            }
+        }
+
+        Ok(Self {
+            automaton: AhoCorasick::builder()
+                .match_kind(MatchKind::LeftmostLongest)
+                .build(patterns)?,
+            canonical,
+        })
+    }
+
+    pub fn apply(&self, text: &str) -> String {
+        self.automaton.replace_all(text, &self.canonical)
+    }
+
+    /// Where each byte of `self.apply(text)` comes from, without building the canonical form
+    /// again.
+    pub fn origins(&self, text: &str) -> Origins {
+        let mut origins = Vec::with_capacity(text.len());
+        let mut last = 0;
+        for m in self.automaton.find_iter(text) {
+            origins.extend((last..m.start()).map(|i| i..i + 1));
+            let len = self.canonical[m.pattern().as_usize()].len();
+            origins.extend(std::iter::repeat_n(m.range(), len));
+            last = m.end();
+        }
+        origins.extend((last..text.len()).map(|i| i..i + 1));
+        Origins(origins)
+    }
+}
+
+/// The part of the original text each byte of a rewritten one comes from, to map ranges
+/// found in the rewritten text back to the original.
+pub struct Origins(Vec<Range<usize>>);
+
+impl Origins {
+    pub fn map(&self, ranges: &[Range<usize>]) -> Vec<Range<usize>> {
+        ranges
+            .iter()
+            .filter(|r| !r.is_empty())
+            .map(|r| self.0[r.start].start..self.0[r.end - 1].end)
+            .collect()
+    }
+}
+
+/// Replaces every match of `automaton` by the replacement for its pattern, each byte of which
+/// comes from the whole match.
+pub fn replace_with_origins<'a>(
+    automaton: &AhoCorasick,
+    text: &str,
+    replacement: impl Fn(usize) -> &'a str,
+) -> (String, Origins) {
+    let mut replaced = String::with_capacity(text.len());
+    let mut origins = Vec::with_capacity(text.len());
+    let mut last = 0;
+    for m in automaton.find_iter(text) {
+        replaced.push_str(&text[last..m.start()]);
+        origins.extend((last..m.start()).map(|i| i..i + 1));
+        let replacement = replacement(m.pattern().as_usize());
+        replaced.push_str(replacement);
+        origins.extend(std::iter::repeat_n(m.range(), replacement.len()));
+        last = m.end();
+    }
+    replaced.push_str(&text[last..]);
+    origins.extend((last..text.len()).map(|i| i..i + 1));
+    (replaced, Origins(origins))
+}
+
+impl Folding {
+    pub const fn is_noop(self) -> bool {
+        !self.ignore_separators && !self.collapse_repeats
+    }
+
+    pub fn apply(self, text: &str) -> String {
+        let mut folded = String::with_capacity(text.len());
+        self.fold_into(text, &mut folded);
+        folded
+    }
+
+    /// Folds the sequences and lookalikes of `confusables` like the usernames, since e.g. `vv`
+    /// never occurs in names with repeated letters collapsed.
+    pub fn apply_to_confusables(self, confusables: &[Confusable]) -> Vec<Confusable> {
+        confusables
+            .iter()
+            .map(|c| Confusable {
+                sequence: self.apply(&c.sequence),
+                lookalikes: c
+                    .lookalikes
+                    .iter()
+                    .map(|l| self.apply(l))
+                    .filter(|l| !l.is_empty())
+                    .collect(),
+            })
+            .collect()
+    }
+
+    /// Like [`Folding::apply`], repeated characters come from the whole run.
+    pub fn apply_with_origins(self, text: &str) -> (String, Origins) {
+        let mut folded = String::with_capacity(text.len());
+        let mut origins: Vec<Range<usize>> = Vec::with_capacity(text.len());
+        let mut last = None;
+        for (i, c) in text.char_indices() {
+            if self.ignore_separators && (c == '_' || c == '-') {
+                continue;
+            }
+            if self.collapse_repeats && last == Some(c) {
+                for origin in &mut origins[folded.len() - c.len_utf8()..] {
+                    origin.end = i + c.len_utf8();
+                }
+                continue;
+            }
+            folded.push(c);
+            origins.extend(std::iter::repeat_n(i..i + c.len_utf8(), c.len_utf8()));
+            last = Some(c);
+        }
+        (folded, Origins(origins))
+    }
+
+    /// Appends `text` without separators and with runs of the same character shortened
+    /// to one, depending on the options.
+    fn fold_into(self, text: &str, out: &mut String) {
+        let mut last = None;
+        for c in text.chars() {
+            if self.ignore_separators && (c == '_' || c == '-') {
+                continue;
+            }
+            if self.collapse_repeats && last == Some(c) {
+                continue;
+            }
+            out.push(c);
+            last = Some(c);
+        }
+    }
+}
+
+/// The folded ids of all users of a corpus, in the same order, so they don't need to be
+/// folded again for every search.
+pub struct FoldedIds {
+    pub folding: Folding,
+    arena: String,
+    ends: Vec<u32>,
+}
+
+impl FoldedIds {
+    pub fn new(corpus: &Corpus, folding: Folding) -> Self {
+        let parts: Vec<(String, Vec<u32>)> = corpus
+            .par_chunks(1 << 20)
+            .map(|users| {
+                let mut arena = String::new();
+                let mut ends = Vec::new();
+                for user in users {
+                    folding.fold_into(user.id, &mut arena);
+                    ends.push(arena.len() as u32);
+                }
+                (arena, ends)
+            })
+            .collect();
+
+        let mut arena = String::with_capacity(parts.iter().map(|(a, _)| a.len()).sum());
+        let mut ends = Vec::with_capacity(corpus.len());
+        for (part, part_ends) in parts {
+            let offset = arena.len() as u32;
+            arena.push_str(&part);
+            ends.extend(part_ends.into_iter().map(|end| offset + end));
+        }
+        Self {
+            folding,
+            arena,
+            ends,
+        }
+    }
+
+    /// Folded id of the user at `index` in the corpus.
+    pub fn get(&self, index: usize) -> &str {
+        let start = index.checked_sub(1).map_or(0, |i| self.ends[i]);
+        &self.arena[start as usize..self.ends[index] as usize]
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::single_range_in_vec_init)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn canonical_forms() {
+        let canonicalizer = Canonicalizer::new(&Confusable::defaults()).unwrap();
+        assert_eq!(canonicalizer.apply("n1gg3r"), "nigger");
+        assert_eq!(canonicalizer.apply("n|gger"), "nigger");
+        assert_eq!(canonicalizer.apply("phuck"), "fuck");
+        // Longer sequences win over the letters they consist of
+        assert_eq!(canonicalizer.apply("vvanker"), "wanker");
+        assert_eq!(canonicalizer.apply("vanker"), "uanker");
+    }
+
+    #[test]
+    fn classes_merge() {
+        let canonicalizer = Canonicalizer::new(&Confusable::parse_table("a 4\n4 @\nb 8")).unwrap();
+        assert_eq!(canonicalizer.apply("@4ab8"), "aaabb");
+    }
+
+    const ALL: Folding = Folding {
+        ignore_separators: true,
+        collapse_repeats: true,
+    };
+
+    #[test]
+    fn folding() {
+        assert_eq!(Folding::default().apply("f__uuck"), "f__uuck");
+        assert_eq!(ALL.apply("f__uuck-_-kk"), "fuck");
+        let separators = Folding {
+            ignore_separators: true,
+            ..Folding::default()
+        };
+        assert_eq!(separators.apply("f__uuck"), "fuuck");
+        // Repeats are collapsed after removing separators
+        assert_eq!(ALL.apply("a_a-b"), "ab");
+    }
+
+    #[test]
+    fn folding_origins() {
+        let (folded, origins) = ALL.apply_with_origins("x_fuuu-ck");
+        assert_eq!(folded, "xfuck");
+        assert_eq!(origins.map(&[1..3]), [2..6]);
+        assert_eq!(origins.map(&[2..5]), [3..9]);
+    }
+
+    #[test]
+    fn folded_confusables() {
+        let folded = ALL.apply_to_confusables(&Confusable::parse_table("w vv uu _\nf ph"));
+        assert_eq!(folded[0].sequence, "w");
+        assert_eq!(folded[0].lookalikes, ["v", "u"]);
+        assert_eq!(folded[1].lookalikes, ["ph"]);
+    }
+
+    #[test]
+    fn folded_ids() {
+        let mut builder = crate::model::CorpusBuilder::default();
+        let source = builder.add_source(std::path::Path::new("names.txt"));
+        for name in ["Foo__Bar", "aaa-b", "xy"] {
+            builder.push(name, source).unwrap();
+        }
+        let corpus = builder.finish().0;
+        let folded = FoldedIds::new(&corpus, ALL);
+        assert_eq!(folded.get(0), "fobar");
+        assert_eq!(folded.get(1), "ab");
+        assert_eq!(folded.get(2), "xy");
+    }
+
+    #[test]
+    fn canonical_origins() {
+        let canonicalizer = Canonicalizer::new(&Confusable::defaults()).unwrap();
+        let origins = canonicalizer.origins("xphvvx");
+        assert_eq!(canonicalizer.apply("xphvvx"), "xfwx");
+        assert_eq!(origins.0.len(), 4);
+        assert_eq!(origins.map(&[1..3]), [1..5]);
+        assert_eq!(origins.map(&[0..1, 3..4, 2..2]), [0..1, 5..6]);
+    }
+}
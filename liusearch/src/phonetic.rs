@@ -0,0 +1,90 @@
+//! A simplified Metaphone encoding for finding names that are spelled differently but sound
+//! alike, e.g. `phuk` and `fuck` both encode to `FK`.
+//!
+//! Unlike Metaphone, each letter's code only depends on its immediate neighbours, so the code
+//! of a substring is (almost always) a substring of the code of the whole name and patterns
+//! can be searched for inside encoded usernames.
+
+/// Encodes the letters of `text`, ignoring everything else.
+pub fn encode(text: &str) -> String {
+    let letters: Vec<u8> = text
+        .bytes()
+        .filter(u8::is_ascii_alphabetic)
+        .map(|b| b.to_ascii_lowercase())
+        .collect();
+    let at = |i: usize| letters.get(i).copied().unwrap_or_default();
+    let is_vowel = |c: u8| matches!(c, b'a' | b'e' | b'i' | b'o' | b'u');
+    let is_soft = |c: u8| matches!(c, b'e' | b'i' | b'y');
+
+    let mut code = String::with_capacity(letters.len());
+    let mut last = "";
+    let mut i = 0;
+    while i < letters.len() {
+        let (next, after) = (at(i + 1), at(i + 2));
+        // Code of the letter and how many letters it consumes
+        let (sound, len) = match at(i) {
+            b'b' => ("B", 1),
+            b'c' if next == b'h' => ("X", 2),
+            b'c' if is_soft(next) => ("S", 1),
+            b'c' if next == b'k' => ("K", 2),
+            b'c' | b'k' | b'q' => ("K", 1),
+            b'd' if next == b'g' && is_soft(after) => ("J", 2),
+            b'd' | b't' if next == b'h' => ("0", 2),
+            b'd' | b't' => ("T", 1),
+            b'f' | b'v' => ("F", 1),
+            b'g' if next == b'h' => ("", 2),
+            b'g' if next == b'g' => ("K", 2),
+            b'g' if is_soft(next) => ("J", 1),
+            b'g' => ("K", 1),
+            b'j' => ("J", 1),
+            b'l' => ("L", 1),
+            b'm' => ("M", 1),
+            b'n' => ("N", 1),
+            b'p' if next == b'h' => ("F", 2),
+            b'p' => ("P", 1),
+            b'r' => ("R", 1),
+            b's' if next == b'h' => ("X", 2),
+            b's' | b'z' => ("S", 1),
+            b'w' | b'y' if is_vowel(next) => ("W", 1),
+            b'x' => ("KS", 1),
+            // Vowels, h and w/y before consonants are silent
+            _ => ("", 1),
+        };
+        // Doubled letters sound like single ones, the same sound separated by a vowel doesn't
+        if sound != last {
+            code += sound;
+        }
+        last = sound;
+        i += len;
+    }
+    code
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn alike() {
+        for (a, b, code) in [
+            ("phuk", "fuck", "FK"),
+            ("cunt", "kunt", "KNT"),
+            ("sh1t", "shiit", "XT"),
+            ("bitch", "biatch", "BTX"),
+            ("vagina", "fajyna", "FJN"),
+        ] {
+            assert_eq!(encode(a), code, "{a}");
+            assert_eq!(encode(b), code, "{b}");
+        }
+    }
+
+    #[test]
+    fn repeats() {
+        assert_eq!(encode("kka"), "K");
+        assert_eq!(encode("kaka"), "KK");
+        assert_eq!(encode("tatt"), "TT");
+        assert_eq!(encode("ckcck"), "K");
+        assert_eq!(encode("hunter"), "NTR");
+        assert_eq!(encode("want"), "WNT");
+    }
+}
@@ -0,0 +1,318 @@
+//! Boolean queries combining several patterns, e.g. `ass NOT (pass OR bass OR class)`.
+//!
+//! Patterns next to each other must all match, `OR` has the lowest precedence. A pattern can
+//! be quoted to include spaces, parentheses or keywords and prefixed with a search mode
+//! (`plain:`, `sim:`, `lev:`, `simlev:`, `phon:` or `re:`), e.g. `lev:bitch` or `re:"^a(b|c)"`.
+
+use std::iter::{Enumerate, Peekable};
+
+use anyhow::anyhow;
+
+use crate::model::SearchMode;
+
+pub enum Query {
+    Pattern(SearchMode, String),
+    And(Vec<Query>),
+    Or(Vec<Query>),
+    Not(Box<Query>),
+}
+
+impl Query {
+    /// Whether a name has to match at least one pattern to match the query. Queries like
+    /// `NOT x` match almost every name.
+    pub fn is_positive(&self) -> bool {
+        match self {
+            Self::Pattern(..) => true,
+            Self::And(parts) => parts.iter().any(Self::is_positive),
+            Self::Or(parts) => parts.iter().all(Self::is_positive),
+            Self::Not(part) => matches!(&**part, Self::Not(part) if part.is_positive()),
+        }
+    }
+}
+
+#[derive(PartialEq)]
+enum Token {
+    Open,
+    Close,
+    And,
+    Or,
+    Not,
+    Pattern(SearchMode, String),
+}
+
+/// Errors name the column they occurred at.
+pub fn parse(input: &str) -> anyhow::Result<Query> {
+    let mut tokens = Tokens {
+        tokens: tokenize(input)?.into_iter().peekable(),
+        end: input.chars().count(),
+    };
+    let query = parse_or(&mut tokens)?;
+    match tokens.next() {
+        None => Ok(query),
+        Some((at, Token::Close)) => Err(error(at, "Unmatched closing parenthesis")),
+        Some((at, _)) => Err(error(at, "Unexpected token after query")),
+    }
+}
+
+/// Tokens with the index of the character they start at.
+struct Tokens {
+    tokens: Peekable<std::vec::IntoIter<(usize, Token)>>,
+    /// Number of characters of the input.
+    end: usize,
+}
+
+impl Tokens {
+    fn next(&mut self) -> Option<(usize, Token)> {
+        self.tokens.next()
+    }
+
+    fn peek(&mut self) -> Option<&Token> {
+        self.tokens.peek().map(|(_, token)| token)
+    }
+
+    fn next_if_eq(&mut self, token: &Token) -> bool {
+        self.tokens.next_if(|(_, t)| t == token).is_some()
+    }
+
+    /// Where the next token starts, or the end of the input.
+    fn position(&mut self) -> usize {
+        self.tokens.peek().map_or(self.end, |&(at, _)| at)
+    }
+}
+
+fn error(at: usize, msg: &str) -> anyhow::Error {
+    anyhow!("{msg} at column {}", at + 1)
+}
+
+fn parse_or(tokens: &mut Tokens) -> anyhow::Result<Query> {
+    let mut alternatives = vec![parse_and(tokens)?];
+    while tokens.next_if_eq(&Token::Or) {
+        alternatives.push(parse_and(tokens)?);
+    }
+    Ok(if alternatives.len() == 1 {
+        alternatives.remove(0)
+    } else {
+        Query::Or(alternatives)
+    })
+}
+
+fn parse_and(tokens: &mut Tokens) -> anyhow::Result<Query> {
+    let mut parts = vec![parse_not(tokens)?];
+    loop {
+        match tokens.peek() {
+            Some(Token::And) => {
+                tokens.next();
+            }
+            Some(Token::Open | Token::Not | Token::Pattern(..)) => (),
+            _ => break,
+        }
+        parts.push(parse_not(tokens)?);
+    }
+    Ok(if parts.len() == 1 {
+        parts.remove(0)
+    } else {
+        Query::And(parts)
+    })
+}
+
+fn parse_not(tokens: &mut Tokens) -> anyhow::Result<Query> {
+    let at = tokens.position();
+    match tokens.next() {
+        Some((_, Token::Not)) => Ok(Query::Not(Box::new(parse_not(tokens)?))),
+        Some((_, Token::Open)) => {
+            let query = parse_or(tokens)?;
+            let close = tokens.position();
+            if !tokens.next_if_eq(&Token::Close) {
+                return Err(error(close, "Missing closing parenthesis"));
+            }
+            Ok(query)
+        }
+        Some((_, Token::Pattern(mode, pattern))) => Ok(Query::Pattern(mode, pattern)),
+        Some((_, Token::Close)) => Err(error(at, "Expected a pattern before closing parenthesis")),
+        Some((_, Token::And | Token::Or)) => Err(error(at, "Expected a pattern before AND/OR")),
+        None => Err(error(at, "Unexpected end of query")),
+    }
+}
+
+type Chars<'a> = Peekable<Enumerate<std::str::Chars<'a>>>;
+
+/// Keywords are case-insensitive since patterns are lowercased before searching.
+fn tokenize(input: &str) -> anyhow::Result<Vec<(usize, Token)>> {
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().enumerate().peekable();
+    while let Some(&(at, c)) = chars.peek() {
+        match c {
+            _ if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' => {
+                chars.next();
+                tokens.push((at, Token::Open));
+            }
+            ')' => {
+                chars.next();
+                tokens.push((at, Token::Close));
+            }
+            '"' => tokens.push((at, Token::Pattern(SearchMode::Plain, quoted(&mut chars)?))),
+            _ => {
+                let word = word(&mut chars);
+                let token = match word.to_ascii_uppercase().as_str() {
+                    "AND" => Token::And,
+                    "OR" => Token::Or,
+                    "NOT" => Token::Not,
+                    _ => match word.split_once(':') {
+                        Some((prefix, rest)) => {
+                            let mode = mode(prefix).map_err(|msg| error(at, &msg))?;
+                            let pattern = if rest.is_empty()
+                                && chars.peek().is_some_and(|&(_, c)| c == '"')
+                            {
+                                quoted(&mut chars)?
+                            } else {
+                                rest.to_string()
+                            };
+                            Token::Pattern(mode, pattern)
+                        }
+                        None => Token::Pattern(SearchMode::Plain, word),
+                    },
+                };
+                tokens.push((at, token));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+fn word(chars: &mut Chars) -> String {
+    let mut word = String::new();
+    while let Some((_, c)) = chars.next_if(|&(_, c)| !c.is_whitespace() && !"()\"".contains(c)) {
+        word.push(c);
+    }
+    word
+}
+
+/// The pattern in the quotes starting at the next character.
+fn quoted(chars: &mut Chars) -> anyhow::Result<String> {
+    let start = chars.next().map_or(0, |(at, _)| at);
+    let mut pattern = String::new();
+    for (_, c) in chars.by_ref() {
+        if c == '"' {
+            if pattern.is_empty() {
+                return Err(error(start, "Empty quotes"));
+            }
+            return Ok(pattern);
+        }
+        pattern.push(c);
+    }
+    Err(error(start, "Missing closing quote"))
+}
+
+fn mode(prefix: &str) -> Result<SearchMode, String> {
+    Ok(match prefix {
+        "plain" => SearchMode::Plain,
+        "sim" => SearchMode::NumberReplacements,
+        "lev" => SearchMode::Levenshtein,
+        "simlev" => SearchMode::SimilarLevenshtein,
+        "phon" => SearchMode::Phonetic,
+        "re" => SearchMode::RegEx,
+        _ => return Err(format!("Unknown search mode {prefix:?}")),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The query as an S-expression with mode prefixes, e.g. `(and a (not lev:b))`.
+    fn show(query: &Query) -> String {
+        let all = |op: &str, parts: &[Query]| {
+            let parts: Vec<_> = parts.iter().map(show).collect();
+            format!("({op} {})", parts.join(" "))
+        };
+        match query {
+            Query::Pattern(mode, pattern) => {
+                let prefix = match mode {
+                    SearchMode::Plain => "",
+                    SearchMode::NumberReplacements => "sim:",
+                    SearchMode::Levenshtein => "lev:",
+                    SearchMode::SimilarLevenshtein => "simlev:",
+                    SearchMode::Phonetic => "phon:",
+                    SearchMode::RegEx => "re:",
+                    _ => "?:",
+                };
+                format!("{prefix}{pattern}")
+            }
+            Query::And(parts) => all("and", parts),
+            Query::Or(parts) => all("or", parts),
+            Query::Not(part) => format!("(not {})", show(part)),
+        }
+    }
+
+    fn parsed(input: &str) -> String {
+        show(&parse(input).unwrap())
+    }
+
+    fn error(input: &str) -> String {
+        parse(input).err().unwrap().to_string()
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(parsed("a b OR c"), "(or (and a b) c)");
+        assert_eq!(parsed("a AND b or c and d"), "(or (and a b) (and c d))");
+        assert_eq!(parsed("NOT a b"), "(and (not a) b)");
+        assert_eq!(parsed("not not a"), "(not (not a))");
+    }
+
+    #[test]
+    fn parentheses() {
+        assert_eq!(parsed("a (b OR c)"), "(and a (or b c))");
+        assert_eq!(parsed("((a))"), "a");
+        assert_eq!(
+            parsed("ass NOT(pass OR bass)"),
+            "(and ass (not (or pass bass)))"
+        );
+    }
+
+    #[test]
+    fn quoting() {
+        assert_eq!(parsed("\"a b\" \"or\""), "(and a b or)");
+        assert_eq!(parsed("\"(x)\""), "(x)");
+        assert_eq!(parsed("re:\"^a(b|c)\" d"), "(and re:^a(b|c) d)");
+    }
+
+    #[test]
+    fn prefixes() {
+        assert_eq!(
+            parsed("plain:a sim:b lev:c simlev:d phon:e re:f"),
+            "(and a sim:b lev:c simlev:d phon:e re:f)"
+        );
+        assert_eq!(parsed("re:a:b"), "re:a:b");
+    }
+
+    #[test]
+    fn error_positions() {
+        assert_eq!(error("a b)"), "Unmatched closing parenthesis at column 4");
+        assert_eq!(error("(a b"), "Missing closing parenthesis at column 5");
+        assert_eq!(
+            error("a OR OR b"),
+            "Expected a pattern before AND/OR at column 6"
+        );
+        assert_eq!(
+            error("a ()"),
+            "Expected a pattern before closing parenthesis at column 4"
+        );
+        assert_eq!(error("a NOT"), "Unexpected end of query at column 6");
+        assert_eq!(error("a \"b"), "Missing closing quote at column 3");
+        assert_eq!(error("a \"\""), "Empty quotes at column 3");
+        assert_eq!(error("é foo:x"), "Unknown search mode \"foo\" at column 3");
+    }
+
+    #[test]
+    fn positive() {
+        assert!(parse("a NOT b").unwrap().is_positive());
+        assert!(parse("NOT NOT a").unwrap().is_positive());
+        assert!(!parse("NOT a").unwrap().is_positive());
+        assert!(!parse("a OR NOT b").unwrap().is_positive());
+        assert!(!parse("NOT a NOT b").unwrap().is_positive());
+    }
+}
@@ -0,0 +1,188 @@
+//! Splitting usernames into words, e.g. `iHateXpeople` into `i hate x people`.
+
+use std::collections::HashSet;
+use std::ops::Range;
+use std::sync::LazyLock;
+
+/// Common English words, one per line.
+static WORDS: LazyLock<HashSet<&'static str>> =
+    LazyLock::new(|| include_str!("../words.txt").lines().collect());
+
+const MAX_WORD_LEN: usize = 20;
+
+pub struct Segmenter {
+    /// Words to recognize besides the bundled ones, e.g. from the term list.
+    extra: HashSet<String>,
+}
+
+impl Segmenter {
+    pub fn new(extra: &[String]) -> Self {
+        Self {
+            extra: extra.iter().map(|w| w.to_ascii_lowercase()).collect(),
+        }
+    }
+
+    fn is_word(&self, word: &str) -> bool {
+        WORDS.contains(word) || self.extra.contains(word)
+    }
+
+    /// Lowercase words of `name`. It is split at separators, digits and camelCase first and
+    /// the parts are then split into as few known words as possible.
+    pub fn segment(&self, name: &str) -> Vec<String> {
+        let mut words = Vec::new();
+        for part in split_parts(name) {
+            if part.starts_with(|c: char| c.is_ascii_digit()) {
+                words.push(part);
+            } else {
+                self.split_words(&part, &mut words);
+            }
+        }
+        words
+    }
+
+    /// Splits a lowercase part into known words with a shortest path over its positions.
+    /// Unknown letters cost more than words, consecutive ones are kept together.
+    fn split_words(&self, part: &str, words: &mut Vec<String>) {
+        const WORD_COST: u32 = 1;
+        const UNKNOWN_COST: u32 = 2;
+
+        let len = part.len();
+        // Cheapest cost to split `part[..i]` and where its last word starts
+        let mut best = vec![(0, 0); len + 1];
+        for end in 1..=len {
+            best[end] = (best[end - 1].0 + UNKNOWN_COST, end - 1);
+            for start in end.saturating_sub(MAX_WORD_LEN)..end {
+                let cost = best[start].0 + WORD_COST;
+                if cost < best[end].0
+                    && part.is_char_boundary(start)
+                    && part.is_char_boundary(end)
+                    && self.is_word(&part[start..end])
+                {
+                    best[end] = (cost, start);
+                }
+            }
+        }
+
+        let mut bounds = Vec::new();
+        let mut end = len;
+        while end > 0 {
+            let start = best[end].1;
+            let known = part.is_char_boundary(start) && self.is_word(&part[start..end]);
+            bounds.push((start, end, known));
+            end = start;
+        }
+
+        let mut unknown = String::new();
+        for &(start, end, known) in bounds.iter().rev() {
+            if known {
+                if !unknown.is_empty() {
+                    words.push(std::mem::take(&mut unknown));
+                }
+                words.push(part[start..end].to_string());
+            } else if let Some(c) = part.get(start..end) {
+                unknown.push_str(c);
+            }
+        }
+        if !unknown.is_empty() {
+            words.push(unknown);
+        }
+    }
+}
+
+/// Byte ranges of the segmented `words` in `name`, see [`Segmenter::segment`].
+pub fn word_ranges(name: &str, words: &[String]) -> Vec<Range<usize>> {
+    let mut chars = name
+        .char_indices()
+        .filter(|&(_, c)| c != '_' && c != '-')
+        .peekable();
+    let mut ranges = Vec::with_capacity(words.len());
+    for word in words {
+        let start = chars.peek().map_or(name.len(), |&(i, _)| i);
+        let mut end = start;
+        let mut len = 0;
+        // Words are lowercase, which can have more characters than the name
+        while len < word.chars().count()
+            && let Some((i, c)) = chars.next()
+        {
+            len += c.to_lowercase().count();
+            end = i + c.len_utf8();
+        }
+        ranges.push(start..end);
+    }
+    ranges
+}
+
+/// Splits at `_` and `-`, between letters and digits and before uppercase letters that start
+/// a word, e.g. `iHateXPeople99` into `i`, `hate`, `x`, `people` and `99`.
+fn split_parts(name: &str) -> Vec<String> {
+    let chars: Vec<char> = name.chars().collect();
+    let mut parts = Vec::new();
+    let mut part = String::new();
+    for (i, &c) in chars.iter().enumerate() {
+        let prev = i.checked_sub(1).map(|i| chars[i]);
+        let next = chars.get(i + 1).copied();
+        let boundary = match prev {
+            None => false,
+            Some(prev) if c.is_ascii_digit() != prev.is_ascii_digit() => true,
+            Some(prev) if c.is_uppercase() && prev.is_lowercase() => true,
+            Some(prev) => {
+                c.is_uppercase() && prev.is_uppercase() && next.is_some_and(char::is_lowercase)
+            }
+        };
+        if (c == '_' || c == '-' || boundary) && !part.is_empty() {
+            parts.push(std::mem::take(&mut part));
+        }
+        if c != '_' && c != '-' {
+            part.extend(c.to_lowercase());
+        }
+    }
+    if !part.is_empty() {
+        parts.push(part);
+    }
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segment(name: &str) -> Vec<String> {
+        Segmenter::new(&["zorg".to_string()]).segment(name)
+    }
+
+    #[test]
+    fn parts() {
+        assert_eq!(
+            split_parts("iHateXPeople99"),
+            ["i", "hate", "x", "people", "99"]
+        );
+        assert_eq!(
+            split_parts("kill_all-the99cats"),
+            ["kill", "all", "the", "99", "cats"]
+        );
+        assert_eq!(split_parts("__"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn words() {
+        assert_eq!(segment("ihatepeople"), ["i", "hate", "people"]);
+        assert_eq!(
+            segment("killallthe99dog"),
+            ["kill", "all", "the", "99", "dog"]
+        );
+        // Unknown letters stay together, extra words are known too
+        assert_eq!(segment("bigqwzcat"), ["big", "qwz", "cat"]);
+        assert_eq!(segment("ZorgDog"), ["zorg", "dog"]);
+    }
+
+    #[test]
+    fn ranges() {
+        let name = "I_HateDOG";
+        let words = segment(name);
+        assert_eq!(words, ["i", "hate", "dog"]);
+        let ranges = word_ranges(name, &words);
+        assert_eq!(ranges, [0..1, 2..6, 6..9]);
+        let found: Vec<_> = ranges.into_iter().map(|r| &name[r]).collect();
+        assert_eq!(found, ["I", "Hate", "DOG"]);
+    }
+}
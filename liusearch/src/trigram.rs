@@ -0,0 +1,208 @@
+//! Index of the trigrams of all usernames, used to only scan names that can contain a literal
+//! instead of the whole corpus.
+
+use regex_syntax::hir::literal::{ExtractKind, Extractor};
+
+use crate::model::Corpus;
+
+/// Characters of usernames, everything else can't occur in ids.
+const ALPHABET: &[u8; 38] = b"abcdefghijklmnopqrstuvwxyz0123456789_-";
+const TRIGRAMS: usize = ALPHABET.len() * ALPHABET.len() * ALPHABET.len();
+
+/// For every trigram the sorted indices of the entries whose id contains it.
+pub struct TrigramIndex {
+    /// Start of the postings of each trigram, plus the end of the last one.
+    offsets: Vec<u32>,
+    postings: Vec<u32>,
+}
+
+impl TrigramIndex {
+    pub fn new(corpus: &Corpus) -> Self {
+        // Counting first allows storing all postings in one allocation
+        let mut offsets = vec![0u32; TRIGRAMS + 1];
+        let mut trigrams = Vec::new();
+        for user in corpus.iter() {
+            for &trigram in unique_trigrams(user.id, &mut trigrams) {
+                offsets[trigram + 1] += 1;
+            }
+        }
+        for i in 1..offsets.len() {
+            offsets[i] += offsets[i - 1];
+        }
+
+        let mut next = offsets.clone();
+        let mut postings = vec![0; offsets[TRIGRAMS] as usize];
+        for (i, user) in corpus.iter().enumerate() {
+            for &trigram in unique_trigrams(user.id, &mut trigrams) {
+                postings[next[trigram] as usize] = i as u32;
+                next[trigram] += 1;
+            }
+        }
+        Self { offsets, postings }
+    }
+
+    fn postings(&self, trigram: usize) -> &[u32] {
+        &self.postings[self.offsets[trigram] as usize..self.offsets[trigram + 1] as usize]
+    }
+
+    /// Sorted indices of all entries that might contain one of `literals`, or `None` if one is
+    /// too short to narrow down the search.
+    pub fn candidates(&self, literals: &[String]) -> Option<Vec<u32>> {
+        let mut candidates = Vec::new();
+        for literal in literals {
+            candidates.extend(self.containing(literal)?);
+        }
+        if literals.len() > 1 {
+            candidates.sort_unstable();
+            candidates.dedup();
+        }
+        Some(candidates)
+    }
+
+    /// Entries containing all trigrams of `literal`.
+    fn containing(&self, literal: &str) -> Option<Vec<u32>> {
+        if literal.len() < 3 {
+            return None;
+        }
+        let Some(mut trigrams) = literal
+            .as_bytes()
+            .windows(3)
+            .map(trigram)
+            .collect::<Option<Vec<_>>>()
+        else {
+            // No id contains characters outside of the alphabet
+            return Some(Vec::new());
+        };
+        trigrams.sort_unstable();
+        trigrams.dedup();
+        // Intersecting starting with the rarest trigram keeps the candidates small
+        trigrams.sort_unstable_by_key(|&t| self.postings(t).len());
+
+        let mut candidates = self.postings(trigrams[0]).to_vec();
+        for &trigram in &trigrams[1..] {
+            let postings = self.postings(trigram);
+            candidates.retain(|i| postings.binary_search(i).is_ok());
+            if candidates.is_empty() {
+                break;
+            }
+        }
+        Some(candidates)
+    }
+}
+
+/// Literals one of which every match of `pattern` starts or ends with, or `None` if there are
+/// none long enough to use the index.
+pub fn regex_literals(pattern: &str) -> Option<Vec<String>> {
+    let hir = regex_syntax::Parser::new().parse(pattern).ok()?;
+    [ExtractKind::Prefix, ExtractKind::Suffix]
+        .into_iter()
+        .filter_map(|kind| {
+            let seq = Extractor::new().kind(kind).extract(&hir);
+            seq.literals()?
+                .iter()
+                .map(|literal| {
+                    let literal = std::str::from_utf8(literal.as_bytes()).ok()?;
+                    (literal.len() >= 3).then(|| literal.to_string())
+                })
+                .collect::<Option<Vec<_>>>()
+        })
+        .min_by_key(Vec::len)
+}
+
+fn trigram(bytes: &[u8]) -> Option<usize> {
+    bytes.iter().try_fold(0, |trigram, b| {
+        let i = ALPHABET.iter().position(|a| a == b)?;
+        Some(trigram * ALPHABET.len() + i)
+    })
+}
+
+fn unique_trigrams<'a>(id: &str, trigrams: &'a mut Vec<usize>) -> &'a [usize] {
+    trigrams.clear();
+    trigrams.extend(id.as_bytes().windows(3).filter_map(trigram));
+    trigrams.sort_unstable();
+    trigrams.dedup();
+    trigrams
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{
+        CorpusBuilder, Exceptions, Folding, LevenshteinSettings, SearchMode, SearchSettings,
+        Searcher,
+    };
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    /// Names from a small alphabet so that patterns have many matches.
+    fn corpus() -> Corpus {
+        let mut builder = CorpusBuilder::default();
+        let source = builder.add_source(std::path::Path::new("names.txt"));
+        let mut rng = StdRng::seed_from_u64(0x2545_f491_4f6c_dd1d);
+        let mut next = |n: u64| rng.gen_range(0..n) as usize;
+        for _ in 0..20_000 {
+            let len = 2 + next(11);
+            let name: String = (0..len).map(|_| b"abcxAB_1-"[next(9)] as char).collect();
+            builder.push(&name, source).unwrap();
+        }
+        builder.finish().0
+    }
+
+    fn check(corpus: &Corpus, index: &TrigramIndex, mode: SearchMode, pattern: &str) {
+        let settings = SearchSettings {
+            mode,
+            lev: LevenshteinSettings::default(),
+            terms: Vec::new(),
+            confusables: Vec::new(),
+            folding: Folding::default(),
+            exceptions: Exceptions::new(),
+        };
+        let searcher = Searcher::new(pattern, &settings).unwrap();
+        let literals = searcher.literals().expect(pattern);
+        let candidates = index.candidates(&literals).expect(pattern);
+        let matches: Vec<u32> = (0..corpus.len() as u32)
+            .filter(|&i| searcher.matches(corpus.get(i as usize).id).is_some())
+            .collect();
+        assert!(!matches.is_empty(), "{pattern}");
+        let missing: Vec<_> = matches
+            .iter()
+            .filter(|i| candidates.binary_search(i).is_err())
+            .collect();
+        assert!(missing.is_empty(), "{pattern} misses {missing:?}");
+    }
+
+    #[test]
+    fn candidates_contain_all_matches() {
+        let corpus = corpus();
+        let index = TrigramIndex::new(&corpus);
+        for pattern in ["abc", "b_1", "-ax", "aaa"] {
+            check(&corpus, &index, SearchMode::Plain, pattern);
+        }
+        for regex in [
+            "abc",
+            "ab(c|x)a",
+            "(abc|xba|1_a)",
+            "[ab]xc",
+            "abc[^x]",
+            "a.c1b",
+            "^abx",
+            "bc1$",
+            "(?i)ABC",
+            "x[0-9]a",
+            "a{2}b+",
+            "(ab|ba)c(x|1)",
+            "bca?x",
+        ] {
+            check(&corpus, &index, SearchMode::RegEx, regex);
+        }
+    }
+
+    #[test]
+    fn short_literals() {
+        let corpus = corpus();
+        let index = TrigramIndex::new(&corpus);
+        assert!(index.candidates(&["ab".to_string()]).is_none());
+        assert_eq!(index.candidates(&["a.b".to_string()]), Some(Vec::new()));
+        assert_eq!(regex_literals("a|bc"), None);
+        assert_eq!(regex_literals("a.c"), None);
+    }
+}
@@ -0,0 +1,303 @@
+//! Edit distance where substituting neighbouring keys or look-alike letters is cheaper than
+//! other substitutions, e.g. `fyck` or `fuvk` for `fuck`.
+
+use std::cell::RefCell;
+use std::ops::Range;
+
+use crate::model::{Confusable, LevenshteinSettings};
+
+const KEYBOARD: [&[u8]; 4] = [b"1234567890-", b"qwertyuiop", b"asdfghjkl", b"zxcvbnm"];
+
+/// Cost and start of the matching part in a row of the dynamic programming table.
+type Cell = (u32, usize);
+
+thread_local! {
+    /// Rows reused by [`NearLetters::distance`], which runs once per name of the corpus.
+    static ROWS: RefCell<[Vec<Cell>; 3]> = RefCell::default();
+}
+
+/// Pairs of bytes with a cheaper substitution.
+pub struct NearLetters {
+    near: Vec<bool>,
+}
+
+impl NearLetters {
+    /// Keys next to each other on a QWERTY keyboard, and single letters that are look-alikes
+    /// of each other in `confusables`.
+    pub fn new(confusables: &[Confusable]) -> Self {
+        let mut near = Self {
+            near: vec![false; 256 * 256],
+        };
+        for (row, keys) in KEYBOARD.iter().enumerate() {
+            for (col, &key) in keys.iter().enumerate() {
+                if let Some(&right) = keys.get(col + 1) {
+                    near.add(key, right);
+                }
+                // Each row is shifted half a key to the right of the one above
+                if let Some(below) = KEYBOARD.get(row + 1) {
+                    for &other in below
+                        .get(col.saturating_sub(1)..below.len().min(col + 1))
+                        .unwrap_or_default()
+                    {
+                        near.add(key, other);
+                    }
+                }
+            }
+        }
+        for confusable in confusables {
+            let letters: Vec<u8> = std::iter::once(&confusable.sequence)
+                .chain(&confusable.lookalikes)
+                .filter(|l| l.len() == 1)
+                .map(|l| l.as_bytes()[0])
+                .collect();
+            for (i, &a) in letters.iter().enumerate() {
+                for &b in &letters[i + 1..] {
+                    near.add(a, b);
+                }
+            }
+        }
+        near
+    }
+
+    fn add(&mut self, a: u8, b: u8) {
+        self.near[a as usize * 256 + b as usize] = true;
+        self.near[b as usize * 256 + a as usize] = true;
+    }
+
+    fn substitution_cost(&self, a: u8, b: u8, costs: &Costs) -> u32 {
+        if a == b {
+            0
+        } else if self.near[a as usize * 256 + b as usize] {
+            costs.near
+        } else {
+            costs.mismatch
+        }
+    }
+
+    /// Distance between `pattern` and `text`, or its best matching part unless
+    /// [`LevenshteinSettings::full_name`], if it's at most `lev.max_k`, with the matching part.
+    pub fn distance(
+        &self,
+        pattern: &[u8],
+        text: &[u8],
+        lev: &LevenshteinSettings,
+    ) -> Option<(u32, Range<usize>)> {
+        let costs = Costs::new(lev);
+        let (gap, swap) = (costs.gap, costs.swap);
+        // Costs to turn the first `i` pattern bytes into text ending at each position and where
+        // that text starts, for the previous two and the current `i`. Matching parts may start
+        // anywhere in the text.
+        let first_row = |j: usize| {
+            if lev.full_name {
+                (j as u32 * gap, 0)
+            } else {
+                (0, j)
+            }
+        };
+        ROWS.with_borrow_mut(|[before, prev, curr]| {
+            before.clear();
+            before.extend((0..=text.len()).map(first_row));
+            prev.clone_from(before);
+            curr.clear();
+            curr.resize(text.len() + 1, (0, 0));
+            for i in 1..=pattern.len() {
+                curr[0] = (i as u32 * gap, 0);
+                for j in 1..=text.len() {
+                    let (a, b) = (pattern[i - 1], text[j - 1]);
+                    let mut best = (
+                        prev[j - 1].0 + self.substitution_cost(a, b, &costs),
+                        prev[j - 1].1,
+                    );
+                    for (cost, start) in [
+                        (prev[j].0 + gap, prev[j].1),
+                        (curr[j - 1].0 + gap, curr[j - 1].1),
+                    ] {
+                        if cost < best.0 {
+                            best = (cost, start);
+                        }
+                    }
+                    if i > 1
+                        && j > 1
+                        && a == text[j - 2]
+                        && pattern[i - 2] == b
+                        && before[j - 2].0 + swap < best.0
+                    {
+                        best = (before[j - 2].0 + swap, before[j - 2].1);
+                    }
+                    curr[j] = best;
+                }
+                // The next row can still be reached by a swap from the previous one
+                let over = |row: &[Cell]| row.iter().all(|&(cost, _)| cost > costs.max_k);
+                if over(prev) && over(curr) {
+                    return None;
+                }
+                std::mem::swap(before, prev);
+                std::mem::swap(prev, curr);
+            }
+
+            let (end, &(k, start)) = if lev.full_name {
+                (text.len(), &prev[text.len()])
+            } else {
+                prev.iter().enumerate().min_by_key(|(_, (cost, _))| *cost)?
+            };
+            (k <= costs.max_k).then_some((k.div_ceil(costs.scale), start..end))
+        })
+    }
+}
+
+/// Costs of [`NearLetters::distance`], with near letters cheaper than other substitutions.
+struct Costs {
+    mismatch: u32,
+    near: u32,
+    gap: u32,
+    swap: u32,
+    max_k: u32,
+    /// What the costs were multiplied by, to divide distances by.
+    scale: u32,
+}
+
+impl Costs {
+    /// Near letters only make a difference if they cost less than other substitutions. If
+    /// they don't, e.g. with all costs at 1, the other costs and the max distance are doubled
+    /// and near letters cost one less than other substitutions.
+    fn new(lev: &LevenshteinSettings) -> Self {
+        let mismatch = u32::from(lev.mismatch_cost);
+        let near = u32::from(lev.near_cost.max(1));
+        let scale = if near < mismatch { 1 } else { 2 };
+        Self {
+            mismatch: mismatch * scale,
+            near: if scale == 1 {
+                near
+            } else {
+                (mismatch * 2).saturating_sub(1)
+            },
+            gap: u32::from(lev.gap_cost) * scale,
+            swap: u32::from(lev.swap_cost) * scale,
+            max_k: lev.max_k.saturating_mul(scale),
+            scale,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use triple_accel::levenshtein::{levenshtein_naive_with_opts, EditCosts};
+
+    fn lev(full_name: bool, max_k: u32) -> LevenshteinSettings {
+        LevenshteinSettings {
+            max_k,
+            full_name,
+            mismatch_cost: 2,
+            near_cost: 1,
+            weighted: true,
+            ..LevenshteinSettings::default()
+        }
+    }
+
+    #[test]
+    fn near_letters() {
+        let near = NearLetters::new(&Confusable::defaults());
+        for (a, b) in [
+            (b'u', b'y'),
+            (b'c', b'v'),
+            (b'p', b'l'),
+            (b'k', b'm'),
+            (b'-', b'p'),
+            (b'a', b'4'),
+        ] {
+            assert!(near.near[a as usize * 256 + b as usize], "{}", a as char);
+            assert!(near.near[b as usize * 256 + a as usize], "{}", b as char);
+        }
+        for (a, b) in [(b'a', b'p'), (b'q', b's'), (b'-', b'o')] {
+            assert!(!near.near[a as usize * 256 + b as usize], "{}", a as char);
+        }
+    }
+
+    #[test]
+    fn near_substitutions() {
+        let near = NearLetters::new(&Confusable::defaults());
+        let lev = lev(false, 2);
+        assert_eq!(near.distance(b"fuck", b"xfyckx", &lev), Some((1, 1..5)));
+        assert_eq!(near.distance(b"fuck", b"fyzk", &lev), None);
+        let lev = LevenshteinSettings {
+            full_name: true,
+            ..lev
+        };
+        assert_eq!(near.distance(b"fuck", b"fyvk", &lev), Some((2, 0..4)));
+        assert_eq!(near.distance(b"fuck", b"fuzk", &lev), Some((2, 0..4)));
+        assert_eq!(near.distance(b"fuck", b"fyzk", &lev), None);
+    }
+
+    #[test]
+    fn default_costs() {
+        let near = NearLetters::new(&Confusable::defaults());
+        let lev = LevenshteinSettings {
+            max_k: 1,
+            full_name: true,
+            weighted: true,
+            ..LevenshteinSettings::default()
+        };
+        assert_eq!(near.distance(b"fuck", b"fyck", &lev), Some((1, 0..4)));
+        assert_eq!(near.distance(b"fuck", b"fuzk", &lev), Some((1, 0..4)));
+        assert_eq!(near.distance(b"fuck", b"fyzk", &lev), None);
+        // Near letters cost half a point with all costs at 1
+        assert_eq!(near.distance(b"fuck", b"fyvk", &lev), Some((1, 0..4)));
+        // A near letter and a gap are still within one difference of two
+        let lev = LevenshteinSettings { max_k: 2, ..lev };
+        assert_eq!(near.distance(b"fuck", b"fyckk", &lev), Some((2, 0..5)));
+    }
+
+    #[test]
+    fn swaps() {
+        let near = NearLetters::new(&[]);
+        let lev = LevenshteinSettings {
+            mismatch_cost: 2,
+            gap_cost: 2,
+            swap_cost: 1,
+            ..lev(false, 1)
+        };
+        assert_eq!(near.distance(b"cab", b"cba", &lev), Some((1, 0..3)));
+        assert_eq!(near.distance(b"cab", b"xcbax", &lev), Some((1, 1..4)));
+        assert_eq!(
+            near.distance(
+                b"abc",
+                b"bac",
+                &LevenshteinSettings {
+                    full_name: true,
+                    ..lev
+                }
+            ),
+            Some((1, 0..3))
+        );
+    }
+
+    /// Without cheaper near letters, whole names have the restricted Damerau-Levenshtein
+    /// distance.
+    #[test]
+    fn matches_damerau_levenshtein() {
+        let near = NearLetters::new(&[]);
+        let mut rng = StdRng::seed_from_u64(0x2545_f491_4f6c_dd1d);
+        let mut next = |n: u64| rng.gen_range(0..n) as usize;
+        for _ in 0..2_000 {
+            let pattern: Vec<u8> = (0..1 + next(6)).map(|_| b"abc"[next(3)]).collect();
+            let text: Vec<u8> = (0..next(8)).map(|_| b"abc"[next(3)]).collect();
+            let (mismatch_cost, gap_cost) = (1 + next(3) as u8, 1 + next(3) as u8);
+            let lev = LevenshteinSettings {
+                mismatch_cost,
+                gap_cost,
+                swap_cost: 1 + next(u64::from(mismatch_cost.min(gap_cost) * 2 - 1)) as u8,
+                near_cost: mismatch_cost,
+                ..lev(true, 4)
+            };
+            let costs = EditCosts::new(lev.mismatch_cost, lev.gap_cost, 0, Some(lev.swap_cost));
+            let (k, _) = levenshtein_naive_with_opts(&pattern, &text, false, costs);
+            assert_eq!(
+                near.distance(&pattern, &text, &lev).map(|(k, _)| k),
+                (k <= lev.max_k).then_some(k),
+                "{pattern:?} {text:?}"
+            );
+        }
+    }
+}